@@ -0,0 +1,106 @@
+use crate::remote::{authorize, request_error, Auth};
+use reqwest::{blocking::Client, StatusCode};
+use std::{
+    io::Error,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+// `~/.cache/mget`, or the platform's equivalent
+pub(crate) fn default_cache_dir() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = match std::env::consts::OS {
+        "windows" => var("LOCALAPPDATA").map(PathBuf::from),
+        "macos" => var("HOME").map(|home| Path::new(&home).join("Library/Caches")),
+        _ => var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".cache"))),
+    };
+    base.map(|base| base.join("mget"))
+}
+
+// the validator and the absolute path of the last complete download of each url, one
+// `url\tvalidator\tpath` line per url
+pub(crate) struct Manifest {
+    path: PathBuf,
+    // concurrent downloads would otherwise drop each other's lines
+    lock: Mutex<()>,
+}
+
+impl Manifest {
+    pub(crate) fn new(dir: &Path) -> Self {
+        Manifest {
+            path: dir.join("manifest"),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn entries(&self) -> Vec<(String, String, String)> {
+        let content = std::fs::read_to_string(&self.path).unwrap_or_default();
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let (url, validator, path) = (fields.next()?, fields.next()?, fields.next()?);
+                Some((url.to_string(), validator.to_string(), path.to_string()))
+            })
+            .collect()
+    }
+
+    // the validator saved for `url`, if it was last saved to `path`
+    pub(crate) fn validator(&self, url: &str, path: &Path) -> Option<String> {
+        let path = std::fs::canonicalize(path).ok()?;
+        self.entries()
+            .into_iter()
+            .find(|(entry_url, _, entry_path)| entry_url == url && Path::new(entry_path) == path)
+            .map(|(_, validator, _)| validator)
+    }
+
+    pub(crate) fn record(&self, url: &str, validator: &str, path: &Path) -> Result<(), Error> {
+        let path = std::fs::canonicalize(path)?;
+        let path = path.to_string_lossy();
+        // a tab or newline would break the line format, such downloads just aren't cached
+        if [url, validator, &path]
+            .iter()
+            .any(|field| field.contains(['\t', '\n', '\r']))
+        {
+            return Ok(());
+        }
+        let _lock = self.lock.lock().unwrap();
+        let mut content = String::new();
+        for (entry_url, entry_validator, entry_path) in self.entries() {
+            if entry_url != url {
+                content += &format!("{}\t{}\t{}\n", entry_url, entry_validator, entry_path);
+            }
+        }
+        content += &format!("{}\t{}\t{}\n", url, validator, path);
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // replaced in one step, so a crash can't leave half a manifest
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, content)?;
+        std::fs::rename(&temp, &self.path)
+    }
+}
+
+// a HEAD with If-None-Match for an ETag or If-Modified-Since for a date
+pub(crate) fn not_modified(
+    client: &Client,
+    url: &str,
+    validator: &str,
+    timeout: Option<Duration>,
+    auth: &Auth,
+) -> Result<bool, Error> {
+    let header = match validator.starts_with('"') {
+        true => reqwest::header::IF_NONE_MATCH,
+        false => reqwest::header::IF_MODIFIED_SINCE,
+    };
+    let mut request = authorize(client.head(url).header(header, validator), auth, url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().map_err(request_error)?;
+    Ok(response.status() == StatusCode::NOT_MODIFIED)
+}
@@ -0,0 +1,172 @@
+use crate::error::MgetError;
+use base64::Engine;
+use reqwest::header::HeaderMap;
+use sha2::Digest;
+use std::{
+    io::{Error, ErrorKind, Read},
+    path::Path,
+};
+
+// an expected digest in hex
+#[derive(Clone)]
+pub(crate) enum Checksum {
+    Sha256(String),
+    Md5(String),
+}
+
+impl Checksum {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let len = match self {
+            Checksum::Sha256(_) => 64,
+            Checksum::Md5(_) => 32,
+        };
+        let hex = self.expected();
+        if hex.len() != len || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid {} checksum: {}", self.name(), hex),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Checksum::Sha256(_) => "SHA-256",
+            Checksum::Md5(_) => "MD5",
+        }
+    }
+
+    fn expected(&self) -> &str {
+        match self {
+            Checksum::Sha256(hex) | Checksum::Md5(hex) => hex,
+        }
+    }
+
+    // S3 and some other servers use the MD5 of the content as ETag, multipart uploads excepted
+    pub(crate) fn from_etag(etag: &str) -> Option<Self> {
+        let checksum = Checksum::Md5(etag.trim_matches('"').to_string());
+        checksum.validate().ok().map(|_| checksum)
+    }
+
+    fn digest(&self, path: &Path) -> Result<String, Error> {
+        match self {
+            Checksum::Sha256(_) => file_digest::<sha2::Sha256>(path),
+            Checksum::Md5(_) => file_digest::<md5::Md5>(path),
+        }
+    }
+
+    pub(crate) fn matches(&self, path: &Path) -> Result<bool, Error> {
+        Ok(self.digest(path)?.eq_ignore_ascii_case(self.expected()))
+    }
+
+    // re-read the whole file, writes arrive out of order so hashing them as they come won't do
+    pub(crate) fn verify(&self, path: &Path) -> Result<(), Error> {
+        let actual = self.digest(path)?;
+        if !actual.eq_ignore_ascii_case(self.expected()) {
+            return Err(MgetError::ChecksumMismatch {
+                algorithm: self.name(),
+                path: path.to_path_buf(),
+                expected: self.expected().to_string(),
+                actual,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+fn file_digest<D: Digest>(path: &Path) -> Result<String, Error> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// a digest of one response body sent along by the server, checked as the body arrives:
+// Content-Digest (RFC 9530) and Content-MD5 cover the body, the older Digest covers the whole
+// file so it only applies to a full response
+pub(crate) struct BodyDigest {
+    name: &'static str,
+    expected: Vec<u8>,
+    hasher: BodyHasher,
+}
+
+enum BodyHasher {
+    Sha256(sha2::Sha256),
+    Md5(md5::Md5),
+}
+
+impl BodyDigest {
+    pub(crate) fn from_headers(headers: &HeaderMap, full: bool) -> Option<Self> {
+        let value = |name: &str| headers.get(name)?.to_str().ok();
+        // `sha-256=:base64:, md5=:base64:`, or without the colons for Digest
+        let pairs = value("content-digest")
+            .or(value("digest").filter(|_| full))
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .filter_map(|pair| {
+                let (algorithm, encoded) = pair.split_once('=')?;
+                Some((algorithm.trim().to_ascii_lowercase(), encoded.trim()))
+            });
+        let mut found = None;
+        for (algorithm, encoded) in pairs {
+            let encoded = encoded.trim_matches(':');
+            match algorithm.as_str() {
+                "sha-256" => found = Some(("SHA-256", encoded)),
+                "md5" if found.is_none() => found = Some(("MD5", encoded)),
+                _ => {}
+            }
+        }
+        let (name, encoded) = found.or(value("content-md5").map(|encoded| ("MD5", encoded)))?;
+        let expected = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .ok()?;
+        let hasher = match name {
+            "SHA-256" => BodyHasher::Sha256(sha2::Sha256::new()),
+            _ => BodyHasher::Md5(md5::Md5::new()),
+        };
+        Some(BodyDigest {
+            name,
+            expected,
+            hasher,
+        })
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match &mut self.hasher {
+            BodyHasher::Sha256(hasher) => hasher.update(data),
+            BodyHasher::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    // `what` names the body in the error, e.g. `bytes 0-1023`
+    pub(crate) fn verify(self, what: &str) -> Result<(), Error> {
+        let actual = match self.hasher {
+            BodyHasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            BodyHasher::Md5(hasher) => hasher.finalize().to_vec(),
+        };
+        match actual == self.expected {
+            true => Ok(()),
+            // most likely damaged on the way, worth another attempt
+            false => Err(Error::new(
+                ErrorKind::ConnectionReset,
+                format!(
+                    "{} of {} doesn't match the digest the server sent",
+                    self.name, what
+                ),
+            )),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Where a [`Downloader`](crate::Downloader) reads the time and how it waits between retries,
+/// for the rate limit and for its threads to stop, [`SystemClock`] unless
+/// [`DownloaderBuilder::clock`] sets another.
+///
+/// A fake clock makes the retry schedule, the speeds and the timeouts reproducible; waiting
+/// for the response data still takes real time.
+///
+/// [`DownloaderBuilder::clock`]: crate::DownloaderBuilder::clock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+
+    /// The time of day the progress lines for logs start with.
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The real time, and sleeping the thread.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration)
+    }
+}
+
+// the random part of the retry waits, so the connections that failed together don't all retry
+// at once; splitmix64, seeded for a reproducible schedule
+pub(crate) struct Jitter(Mutex<u64>);
+
+impl Jitter {
+    pub(crate) fn new(seed: u64) -> Self {
+        Jitter(Mutex::new(seed))
+    }
+
+    // a different seed for every downloader
+    pub(crate) fn random() -> Self {
+        Jitter::new(RandomState::new().build_hasher().finish())
+    }
+
+    // uniform in [0, 1)
+    pub(crate) fn fraction(&self) -> f64 {
+        let mut state = self.0.lock().unwrap();
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_repeats_its_seed() {
+        let draw = |jitter: &Jitter| (0..100).map(|_| jitter.fraction()).collect::<Vec<_>>();
+        let first = draw(&Jitter::new(42));
+        assert_eq!(first, draw(&Jitter::new(42)));
+        assert_ne!(first, draw(&Jitter::new(43)));
+        assert!(first.iter().all(|fraction| (0.0..1.0).contains(fraction)));
+        // spread over the range, not stuck at one end
+        assert!(first.iter().any(|&fraction| fraction < 0.25));
+        assert!(first.iter().any(|&fraction| fraction > 0.75));
+    }
+}
@@ -0,0 +1,155 @@
+use log::Level;
+use std::{
+    fmt::Display,
+    io::{IsTerminal, Write},
+};
+
+// where progress and diagnostics go, stderr when the downloaded bytes go to stdout; with `log`
+// the messages become `log` records and only the progress is printed
+#[derive(Clone, Copy)]
+pub(crate) struct Console {
+    stderr: bool,
+    log: bool,
+}
+
+impl Console {
+    pub(crate) fn new(stderr: bool, log: bool) -> Self {
+        Console { stderr, log }
+    }
+
+    // the start and end of a download and where it was saved
+    pub(crate) fn line(self, msg: impl Display) {
+        self.message(Level::Info, msg);
+    }
+
+    // the details of --verbose: the probe, the split, every chunk
+    pub(crate) fn debug(self, msg: impl Display) {
+        self.message(Level::Debug, msg);
+    }
+
+    // trouble the download recovers from
+    pub(crate) fn warn(self, msg: impl Display) {
+        self.message(Level::Warn, msg);
+    }
+
+    pub(crate) fn error(self, msg: impl Display) {
+        self.message(Level::Error, msg);
+    }
+
+    fn message(self, level: Level, msg: impl Display) {
+        match self.log {
+            true => log::log!(level, "{}", msg),
+            false => self.print(msg),
+        }
+    }
+
+    // progress lines are never log records
+    pub(crate) fn print(self, msg: impl Display) {
+        match self.stderr {
+            true => eprintln!("{}", msg),
+            false => println!("{}", msg),
+        }
+    }
+
+    // a live bar only makes sense on a terminal, redirected output gets plain lines
+    pub(crate) fn is_terminal(self) -> bool {
+        match self.stderr {
+            true => std::io::stderr().is_terminal(),
+            false => std::io::stdout().is_terminal(),
+        }
+    }
+
+    // follows https://no-color.org, only terminals get colors anyway
+    pub(crate) fn color(self) -> bool {
+        self.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+    }
+
+    // redraw the current line in place
+    pub(crate) fn redraw(self, msg: impl Display) {
+        match self.stderr {
+            true => eprint!("\r{}", msg),
+            false => {
+                print!("\r{}", msg);
+                std::io::stdout().flush().ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{serve, temp_path, Response},
+        Downloader,
+    };
+    use log::{LevelFilter, Log, Metadata, Record};
+    use std::sync::Mutex;
+
+    // the records of this crate, the http client logs its own
+    struct Capture(Mutex<Vec<(Level, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target().starts_with("mget_rs")
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                let message = record.args().to_string();
+                self.0.lock().unwrap().push((record.level(), message));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    // the only test that logs, the logger can be set once per process
+    #[test]
+    fn messages_become_records() {
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+        let console = Console::new(true, true);
+        console.line("line");
+        console.debug("debug");
+        console.warn("warn");
+        console.error("error");
+        let records = std::mem::take(&mut *CAPTURE.0.lock().unwrap());
+        let expected = [
+            (Level::Info, "line"),
+            (Level::Debug, "debug"),
+            (Level::Warn, "warn"),
+            (Level::Error, "error"),
+        ];
+        assert_eq!(records.len(), expected.len());
+        for ((level, message), (expected_level, expected)) in records.iter().zip(expected) {
+            assert_eq!((*level, message.as_str()), (expected_level, expected));
+        }
+
+        let url = serve(|request| Response::ranged(request, &[b'a'; 100]));
+        let output = temp_path("logged.bin");
+        let downloader = |verbose| {
+            Downloader::builder()
+                .output(output.to_string_lossy())
+                .log(true)
+                .verbose(verbose)
+                .cache(false)
+                .build()
+                .unwrap()
+        };
+        downloader(true).download(&url).unwrap();
+        let records = std::mem::take(&mut *CAPTURE.0.lock().unwrap());
+        let logged = |level| records.iter().filter(move |record| record.0 == level);
+        assert!(logged(Level::Info).any(|(_, message)| message.starts_with("Downloading ")));
+        assert!(logged(Level::Debug).count() > 0);
+        assert_eq!(logged(Level::Error).count(), 0);
+        // without --verbose there are no debug records
+        downloader(false).download(&url).unwrap();
+        let records = std::mem::take(&mut *CAPTURE.0.lock().unwrap());
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|(level, _)| *level <= Level::Info));
+        std::fs::remove_file(output).ok();
+    }
+}
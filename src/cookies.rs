@@ -0,0 +1,209 @@
+use reqwest::{cookie::CookieStore, header::HeaderValue, Url};
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+struct Cookie {
+    domain: String,
+    // false for a cookie without a Domain attribute, which only goes back to the same host
+    subdomains: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    // unix seconds, 0 for a session cookie
+    expires: u64,
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    fn matches(&self, url: &Url, now: u64) -> bool {
+        let host = url.host_str().unwrap_or_default();
+        let domain = match self.subdomains {
+            true => host == self.domain || host.ends_with(&format!(".{}", self.domain)),
+            false => host == self.domain,
+        };
+        let path = url.path() == self.path
+            || (url.path().starts_with(&self.path)
+                && (self.path.ends_with('/') || url.path()[self.path.len()..].starts_with('/')));
+        domain && path && (!self.secure || url.scheme() == "https") && !self.expired(now)
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        self.expires != 0 && self.expires <= now
+    }
+}
+
+// cookies set by responses are sent with the later requests of every download, and kept in a
+// Netscape cookie file when one is given
+pub(crate) struct CookieJar {
+    // `name=value` pairs sent to every url, from the builder and Cookie headers
+    fixed: Vec<String>,
+    cookies: Mutex<Vec<Cookie>>,
+    path: Option<PathBuf>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl CookieJar {
+    // a missing file starts an empty jar, it is created on the first save
+    pub(crate) fn new(fixed: Vec<String>, path: Option<PathBuf>) -> Result<Self, Error> {
+        let content = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    return Err(Error::new(
+                        e.kind(),
+                        format!("Reading cookie file {}: {}", path.display(), e),
+                    ))
+                }
+            },
+            None => String::new(),
+        };
+        let mut cookies = Vec::new();
+        for line in content.lines() {
+            // curl marks HttpOnly cookies with a prefix on an otherwise commented line
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+                continue;
+            };
+            cookies.push(Cookie {
+                domain: domain.trim_start_matches('.').to_string(),
+                subdomains: subdomains == "TRUE",
+                path: path.to_string(),
+                secure: secure == "TRUE",
+                http_only,
+                expires: expires.parse().unwrap_or(0),
+                name: name.to_string(),
+                value: value.to_string(),
+            });
+        }
+        Ok(CookieJar {
+            fixed,
+            cookies: Mutex::new(cookies),
+            path,
+        })
+    }
+
+    pub(crate) fn save(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let now = now();
+        let mut content = String::from("# Netscape HTTP Cookie File\n");
+        for cookie in self.cookies.lock().unwrap().iter() {
+            if cookie.expired(now) {
+                continue;
+            }
+            let flag = |set: bool| if set { "TRUE" } else { "FALSE" };
+            content += &format!(
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                if cookie.subdomains { "." } else { "" },
+                cookie.domain,
+                flag(cookie.subdomains),
+                cookie.path,
+                flag(cookie.secure),
+                cookie.expires,
+                cookie.name,
+                cookie.value
+            );
+        }
+        std::fs::write(path, content)
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let host = url.host_str().unwrap_or_default();
+        let now = now();
+        let mut cookies = self.cookies.lock().unwrap();
+        for header in headers {
+            let Some(parsed) = header
+                .to_str()
+                .ok()
+                .and_then(|header| cookie::Cookie::parse(header).ok())
+            else {
+                continue;
+            };
+            let domain = parsed.domain().map(|domain| domain.to_ascii_lowercase());
+            // a server can only set cookies for itself and its parent domains
+            if let Some(domain) = &domain {
+                if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                    continue;
+                }
+            }
+            // the directory of the url by default
+            let path = match parsed.path() {
+                Some(path) if path.starts_with('/') => path.to_string(),
+                _ => match url.path().rfind('/') {
+                    Some(0) | None => "/".to_string(),
+                    Some(end) => url.path()[..end].to_string(),
+                },
+            };
+            let expires = match (parsed.max_age(), parsed.expires_datetime()) {
+                (Some(max_age), _) if max_age.whole_seconds() <= 0 => 1,
+                (Some(max_age), _) => now + max_age.whole_seconds() as u64,
+                (None, Some(expires)) => expires.unix_timestamp().max(1) as u64,
+                (None, None) => 0,
+            };
+            let cookie = Cookie {
+                subdomains: domain.is_some(),
+                domain: domain.unwrap_or_else(|| host.to_string()),
+                path,
+                secure: parsed.secure().unwrap_or(false),
+                http_only: parsed.http_only().unwrap_or(false),
+                expires,
+                name: parsed.name().to_string(),
+                value: parsed.value().to_string(),
+            };
+            // a cookie replaces the one with the same name, domain and path, an expired one
+            // just removes it
+            cookies.retain(|other| {
+                (&other.name, &other.domain, &other.path)
+                    != (&cookie.name, &cookie.domain, &cookie.path)
+            });
+            if !cookie.expired(now) {
+                cookies.push(cookie);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let now = now();
+        let cookies = self.cookies.lock().unwrap();
+        let pairs: Vec<String> = self
+            .fixed
+            .iter()
+            .cloned()
+            .chain(
+                cookies
+                    .iter()
+                    .filter(|cookie| cookie.matches(url, now))
+                    .map(|cookie| format!("{}={}", cookie.name, cookie.value)),
+            )
+            .collect();
+        if pairs.is_empty() {
+            return None;
+        }
+        let mut value = HeaderValue::from_str(&pairs.join("; ")).ok()?;
+        // session cookies are as good as credentials
+        value.set_sensitive(true);
+        Some(value)
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    remote::RemoteFile,
+    worker::{forward_body, Task, TaskContext, TaskResult},
+};
+use percent_encoding::percent_decode;
+use reqwest::Url;
+use std::{
+    io::{Error, ErrorKind},
+    sync::mpsc::SyncSender,
+};
+
+// what a `data:` url without a media type holds, per RFC 2397
+const DEFAULT_MEDIA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+pub(crate) fn is_data(url: &Url) -> bool {
+    url.scheme() == "data"
+}
+
+// `data:[<media type>][;base64],<data>`, the payload percent-encoded or in base64; returns the
+// bytes and the media type
+pub(crate) fn decode(url: &Url) -> Result<(Vec<u8>, String), Error> {
+    use base64::Engine;
+    let invalid = |message: &str| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid data url: {}", message),
+        )
+    };
+    // a `?` is part of the payload, only a `#` ends it
+    let mut content = url.path().to_string();
+    if let Some(query) = url.query() {
+        content.push('?');
+        content.push_str(query);
+    }
+    let (header, payload) = content
+        .split_once(',')
+        .ok_or_else(|| invalid("no comma before the data"))?;
+    let header = percent_decode(header.as_bytes()).decode_utf8_lossy();
+    let (media_type, base64) = match header.rsplit_once(';') {
+        Some((media_type, last)) if last.trim().eq_ignore_ascii_case("base64") => {
+            (media_type.trim(), true)
+        }
+        _ => (header.trim(), false),
+    };
+    let media_type = match media_type {
+        "" => DEFAULT_MEDIA_TYPE.to_string(),
+        // only parameters like `;charset=utf-8`
+        media_type if media_type.starts_with(';') => format!("text/plain{}", media_type),
+        media_type if !media_type.contains('/') => {
+            return Err(invalid(&format!(
+                "media type {:?} has no subtype",
+                media_type
+            )))
+        }
+        media_type => media_type.to_string(),
+    };
+    let bytes: Vec<u8> = percent_decode(payload.as_bytes()).collect();
+    let bytes = match base64 {
+        true => {
+            // line breaks and spaces are allowed in the encoded text, padding may be left out
+            let encoded: Vec<u8> = bytes
+                .into_iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+            let engine = base64::engine::GeneralPurpose::new(
+                &base64::alphabet::STANDARD,
+                base64::engine::GeneralPurposeConfig::new()
+                    .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent),
+            );
+            engine
+                .decode(encoded)
+                .map_err(|e| invalid(&format!("bad base64: {}", e)))?
+        }
+        false => bytes,
+    };
+    Ok((bytes, media_type))
+}
+
+// the payload is in the url itself, nothing to ask for; it is split like a local file
+pub(crate) fn get_file_size(url: &Url) -> Result<RemoteFile, Error> {
+    let (bytes, media_type) = decode(url)?;
+    Ok(RemoteFile {
+        url: url.to_string(),
+        size: Some(bytes.len() as u64),
+        accept_ranges: true,
+        file_name: None,
+        content_type: Some(media_type),
+        validator: None,
+        checksums: Vec::new(),
+        version: None,
+    })
+}
+
+// decode the payload again and read `pos` to `end` of it
+pub(crate) fn fetch_range(
+    ctx: &TaskContext,
+    tx: &SyncSender<TaskResult>,
+    task: &Task,
+    pos: &mut u64,
+    end: u64,
+) -> Result<(), Error> {
+    let url = Url::parse(&task.url).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let (bytes, _) = decode(&url)?;
+    let start = std::cmp::min(*pos + ctx.offset, bytes.len() as u64) as usize;
+    forward_body(ctx, tx, task, &mut &bytes[start..], pos, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(url: &str) -> Result<(Vec<u8>, String), Error> {
+        decode(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn base64_payloads() {
+        // url, bytes, media type
+        let cases: [(&str, &[u8], &str); 5] = [
+            (
+                "data:application/octet-stream;base64,AAEC/w==",
+                &[0, 1, 2, 255],
+                "application/octet-stream",
+            ),
+            ("data:;base64,aGVsbG8", b"hello", DEFAULT_MEDIA_TYPE),
+            ("data:text/plain;BASE64,aGVs%0AbG8=", b"hello", "text/plain"),
+            ("data:image/png;base64,", b"", "image/png"),
+            (
+                "data:;charset=utf-8;base64,w6k=",
+                "é".as_bytes(),
+                "text/plain;charset=utf-8",
+            ),
+        ];
+        for (url, bytes, media_type) in cases {
+            let (decoded, decoded_type) = decoded(url).unwrap();
+            assert_eq!(
+                (decoded.as_slice(), decoded_type.as_str()),
+                (bytes, media_type)
+            );
+        }
+    }
+
+    #[test]
+    fn percent_encoded_payloads() {
+        let cases: [(&str, &[u8], &str); 4] = [
+            ("data:,hello%20world", b"hello world", DEFAULT_MEDIA_TYPE),
+            ("data:text/csv,a,b%0A1,2", b"a,b\n1,2", "text/csv"),
+            ("data:,100%25?x=1", b"100%?x=1", DEFAULT_MEDIA_TYPE),
+            (
+                "data:application/octet-stream,%00%FF",
+                &[0, 255],
+                "application/octet-stream",
+            ),
+        ];
+        for (url, bytes, media_type) in cases {
+            let (decoded, decoded_type) = decoded(url).unwrap();
+            assert_eq!(
+                (decoded.as_slice(), decoded_type.as_str()),
+                (bytes, media_type)
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_urls() {
+        let cases = [
+            ("data:text/plain", "no comma before the data"),
+            ("data:;base64,a$b", "bad base64"),
+            ("data:plain,x", "media type \"plain\" has no subtype"),
+        ];
+        for (url, message) in cases {
+            let error = decoded(url).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            let expected = format!("Invalid data url: {}", message);
+            assert!(error.to_string().starts_with(&expected), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use reqwest::StatusCode;
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+/// Why a download failed, returned by the [`Downloader`](crate::Downloader) methods.
+#[derive(Debug)]
+pub enum MgetError {
+    /// The server answered with an unsuccessful status.
+    Http {
+        status: StatusCode,
+        url: String,
+        /// The status with the requested range and the start of the error page.
+        message: String,
+    },
+    /// The request couldn't be sent or its response not read.
+    Network(reqwest::Error),
+    /// The downloaded file doesn't match an expected checksum.
+    ChecksumMismatch {
+        /// `SHA-256` or `MD5`.
+        algorithm: &'static str,
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// The server answered a range request with the whole file.
+    RangeUnsupported { url: String },
+    /// Anything else: local files, invalid options, FTP, an interrupt, ...
+    Io(Error),
+}
+
+impl MgetError {
+    /// The closest io kind, e.g. `Interrupted` when stopped by the stop flag.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            MgetError::Network(e) if e.is_timeout() => ErrorKind::TimedOut,
+            MgetError::Network(_) => ErrorKind::ConnectionReset,
+            MgetError::RangeUnsupported { .. } => ErrorKind::Unsupported,
+            MgetError::Io(e) => e.kind(),
+            _ => ErrorKind::InvalidData,
+        }
+    }
+
+    /// The HTTP status of an [`MgetError::Http`].
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            MgetError::Http { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MgetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MgetError::Http { message, .. } => f.write_str(message),
+            MgetError::Network(e) => e.fmt(f),
+            MgetError::ChecksumMismatch {
+                algorithm,
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} mismatch for {}: expected {}, got {}",
+                algorithm,
+                path.display(),
+                expected,
+                actual
+            ),
+            MgetError::RangeUnsupported { .. } => f.write_str("Server ignored the range request"),
+            MgetError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MgetError {
+    // the wrapped errors are displayed as they are, so their sources are this one's
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MgetError::Network(e) => e.source(),
+            MgetError::Io(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+// the internals pass io::Error around, an MgetError inside one comes out unchanged
+impl From<Error> for MgetError {
+    fn from(e: Error) -> Self {
+        match e.get_ref().is_some_and(|inner| inner.is::<MgetError>()) {
+            true => *e.into_inner().unwrap().downcast().unwrap(),
+            false => MgetError::Io(e),
+        }
+    }
+}
+
+impl From<MgetError> for Error {
+    fn from(e: MgetError) -> Self {
+        match e {
+            MgetError::Io(e) => e,
+            e => Error::new(e.kind(), e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for MgetError {
+    fn from(e: reqwest::Error) -> Self {
+        MgetError::Network(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survives_the_io_error_internals() {
+        let error = MgetError::RangeUnsupported {
+            url: "http://example.com/".to_string(),
+        };
+        let io: Error = error.into();
+        assert_eq!(io.kind(), ErrorKind::Unsupported);
+        assert!(matches!(
+            MgetError::from(io),
+            MgetError::RangeUnsupported { url } if url == "http://example.com/"
+        ));
+        // a plain io error is wrapped, and unwrapped again as it was
+        let error = MgetError::from(Error::new(ErrorKind::NotFound, "gone"));
+        assert!(matches!(&error, MgetError::Io(e) if e.kind() == ErrorKind::NotFound));
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(error.status(), None);
+        let io: Error = error.into();
+        assert_eq!(
+            (io.kind(), io.to_string()),
+            (ErrorKind::NotFound, "gone".to_string())
+        );
+    }
+}
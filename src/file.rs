@@ -0,0 +1,61 @@
+use crate::{
+    remote::RemoteFile,
+    worker::{forward_body, Task, TaskContext, TaskResult},
+};
+use reqwest::Url;
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Seek, SeekFrom},
+    path::PathBuf,
+    sync::mpsc::SyncSender,
+};
+
+pub(crate) fn is_file(url: &Url) -> bool {
+    url.scheme() == "file"
+}
+
+pub(crate) fn local_path(url: &Url) -> Result<PathBuf, Error> {
+    url.to_file_path().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a local path", url),
+        )
+    })
+}
+
+// a regular file can be read at any offset, so it is split like a server with range support
+pub(crate) fn get_file_size(url: &Url) -> Result<RemoteFile, Error> {
+    let path = local_path(url)?;
+    let metadata = std::fs::metadata(&path)
+        .map_err(|e| Error::new(e.kind(), format!("Reading {}: {}", path.display(), e)))?;
+    if !metadata.is_file() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a regular file", path.display()),
+        ));
+    }
+    Ok(RemoteFile {
+        url: url.to_string(),
+        size: Some(metadata.len()),
+        accept_ranges: true,
+        file_name: None,
+        content_type: None,
+        validator: None,
+        checksums: Vec::new(),
+        version: None,
+    })
+}
+
+// seek to `pos` and read until `end`, or to the end of the file for a single stream
+pub(crate) fn fetch_range(
+    ctx: &TaskContext,
+    tx: &SyncSender<TaskResult>,
+    task: &Task,
+    pos: &mut u64,
+    end: u64,
+) -> Result<(), Error> {
+    let url = Url::parse(&task.url).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let mut file = File::open(local_path(&url)?)?;
+    file.seek(SeekFrom::Start(*pos + ctx.offset))?;
+    forward_body(ctx, tx, task, &mut file, pos, end)
+}
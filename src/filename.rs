@@ -0,0 +1,358 @@
+use crate::template::today;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use std::path::{Path, PathBuf};
+
+// the name suggested by the server, else the decoded last path segment, else `default_name` or
+// the host and date; a name without an extension gets the one of `content_type`
+pub(crate) fn derive_filename(
+    url: &Url,
+    suggested: Option<&str>,
+    content_type: Option<&str>,
+    default_name: Option<&str>,
+) -> String {
+    if let Some(name) = suggested.and_then(sanitize_filename) {
+        return name;
+    }
+    // `report%20(1).pdf` is saved as `report (1).pdf`, an encoded slash can't escape the directory
+    let segment = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .and_then(|segment| sanitize_filename(&segment));
+    let extension = content_type.and_then(content_type_extension);
+    match (segment, default_name) {
+        // a bare name like `download` gets the extension of what it turned out to be
+        (Some(name), _) => match (name.contains('.'), extension) {
+            (false, Some(extension)) => format!("{}.{}", name, extension),
+            _ => name,
+        },
+        (None, Some(name)) => name.to_string(),
+        (None, None) => format!(
+            "{}-{}.{}",
+            url.host_str()
+                .filter(|host| !host.is_empty())
+                .unwrap_or("download"),
+            today().replace('-', ""),
+            extension.unwrap_or("bin")
+        ),
+    }
+}
+
+// the first of `name.ext`, `name.1.ext`, `name.2.ext`, ... that doesn't exist and isn't
+// `taken`; a dot in the directory isn't an extension
+pub(crate) fn uniquify(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut candidate = path.to_path_buf();
+    let mut index = 1;
+    while std::fs::metadata(&candidate).is_ok() || taken(&candidate) {
+        let numbered = match name.rsplit_once('.') {
+            Some((stem, extension)) => format!("{}.{}.{}", stem, index, extension),
+            None => format!("{}.{}", name, index),
+        };
+        candidate = path.with_file_name(numbered);
+        index += 1;
+    }
+    candidate
+}
+
+// keep only the last path component, so a server can't write outside the output directory;
+// on windows also a name NTFS accepts
+pub(crate) fn sanitize_filename(name: &str) -> Option<String> {
+    let name: String = name
+        .rsplit(['/', '\\'])
+        .next()?
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    let name = match cfg!(windows) {
+        true => windows_filename(&name),
+        false => name,
+    };
+    let name = name.trim();
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.to_string()),
+    }
+}
+
+// the device names windows opens instead of a file, whatever the extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// `a:b?c=1` becomes `a_b_c=1`: the characters NTFS refuses are replaced, the trailing dots and
+// spaces windows drops are removed and a device name like `nul.txt` becomes `_nul.txt`
+pub(crate) fn windows_filename(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    match RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        true => format!("_{}", name),
+        false => name.to_string(),
+    }
+}
+
+// MAX_PATH, counting the terminating nul
+const MAX_PATH: usize = 260;
+
+// a windows path longer than MAX_PATH only opens in the absolute `\\?\` form; elsewhere and for
+// shorter paths it is returned as is
+pub(crate) fn long_path(path: String) -> String {
+    if !cfg!(windows) || path.starts_with(r"\\?\") {
+        return path;
+    }
+    let Ok(absolute) = std::path::absolute(&path) else {
+        return path;
+    };
+    extended_path(&absolute.to_string_lossy()).unwrap_or(path)
+}
+
+// the `\\?\` form of an absolute windows path too long to open otherwise
+fn extended_path(absolute: &str) -> Option<String> {
+    if absolute.encode_utf16().count() < MAX_PATH {
+        return None;
+    }
+    match absolute.strip_prefix(r"\\") {
+        Some(share) => Some(format!(r"\\?\UNC\{}", share)),
+        None => Some(format!(r"\\?\{}", absolute)),
+    }
+}
+
+// media types and their usual extensions
+const MEDIA_TYPES: [(&str, &str); 22] = [
+    ("text/html", "html"),
+    ("text/plain", "txt"),
+    ("text/css", "css"),
+    ("text/csv", "csv"),
+    ("text/xml", "xml"),
+    ("application/xml", "xml"),
+    ("application/json", "json"),
+    ("application/javascript", "js"),
+    ("text/javascript", "js"),
+    ("application/pdf", "pdf"),
+    ("application/zip", "zip"),
+    ("application/gzip", "gz"),
+    ("application/x-gzip", "gz"),
+    ("application/x-tar", "tar"),
+    ("application/x-xz", "xz"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/svg+xml", "svg"),
+    ("image/webp", "webp"),
+    ("audio/mpeg", "mp3"),
+    ("video/mp4", "mp4"),
+];
+
+fn essence(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or_default();
+    essence.trim().to_ascii_lowercase()
+}
+
+// the usual extension of a media type like `text/html; charset=utf-8`, None for a generic
+// `application/octet-stream`
+fn content_type_extension(content_type: &str) -> Option<&'static str> {
+    let essence = essence(content_type);
+    MEDIA_TYPES
+        .iter()
+        .find(|(media_type, _)| *media_type == essence)
+        .map(|(_, extension)| *extension)
+}
+
+// an html page saved under another extension, or a known type under the extension of another
+// one; generic and unknown types and extensions pass, as do text types under each other's
+// extensions
+pub(crate) fn content_type_mismatch(file_name: &str, content_type: &str) -> bool {
+    let Some(extension) = Path::new(file_name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+    else {
+        return false;
+    };
+    let extension = match extension.as_str() {
+        "htm" | "xhtml" => "html",
+        "jpeg" => "jpg",
+        "tgz" => "gz",
+        extension => extension,
+    };
+    let essence = essence(content_type);
+    if essence == "text/html" {
+        return extension != "html";
+    }
+    let Some(expected) = content_type_extension(&essence) else {
+        return false;
+    };
+    let text = |extension: &str| {
+        MEDIA_TYPES
+            .iter()
+            .any(|(media_type, known)| *known == extension && media_type.starts_with("text/"))
+    };
+    MEDIA_TYPES.iter().any(|(_, known)| *known == extension)
+        && extension != expected
+        && !(essence.starts_with("text/") && text(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_filename_cases() {
+        let derive = |url: &str, suggested, content_type, default_name| {
+            let url = Url::parse(url).unwrap();
+            derive_filename(&url, suggested, content_type, default_name)
+        };
+        // path, suggested name, content type, expected
+        let cases = [
+            ("/a/file.zip", None, None, "file.zip"),
+            ("/file.zip?v=2#top", None, None, "file.zip"),
+            ("/report%20(1).pdf", None, None, "report (1).pdf"),
+            ("/a%2F..%2Fb.txt", None, None, "b.txt"),
+            ("/get", Some("real.tar.gz"), None, "real.tar.gz"),
+            ("/get", Some("../../etc/passwd"), None, "passwd"),
+            ("/get", Some(".."), None, "get"),
+            (
+                "/download",
+                None,
+                Some("text/html; charset=utf-8"),
+                "download.html",
+            ),
+            (
+                "/download",
+                None,
+                Some("application/octet-stream"),
+                "download",
+            ),
+            ("/a.txt", None, Some("text/html"), "a.txt"),
+        ];
+        for (path, suggested, content_type, expected) in cases {
+            let url = format!("http://example.com{}", path);
+            assert_eq!(
+                derive(&url, suggested, content_type, None),
+                expected,
+                "{}",
+                path
+            );
+        }
+        // no name in the url: the default name, else the host and date
+        let dated = |host: &str, extension: &str| {
+            format!("{}-{}.{}", host, today().replace('-', ""), extension)
+        };
+        let cases = [
+            ("http://example.com/dir/", None, dated("example.com", "bin")),
+            ("http://example.com/?q=1", None, dated("example.com", "bin")),
+            (
+                "http://example.com",
+                Some("application/json"),
+                dated("example.com", "json"),
+            ),
+            ("file:///", None, dated("download", "bin")),
+        ];
+        for (url, content_type, expected) in cases {
+            assert_eq!(derive(url, None, content_type, None), expected, "{}", url);
+            assert_eq!(
+                derive(url, None, content_type, Some("index.html")),
+                "index.html"
+            );
+        }
+    }
+
+    #[test]
+    fn sanitize_filename_cases() {
+        let cases = [
+            ("file.txt", Some("file.txt")),
+            ("dir/sub/file.txt", Some("file.txt")),
+            (r"C:\Users\x\file.txt", Some("file.txt")),
+            ("  spaced.txt ", Some("spaced.txt")),
+            ("bell\x07.txt", Some("bell.txt")),
+            ("dir/", None),
+            ("..", None),
+            (".", None),
+            ("", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(sanitize_filename(name).as_deref(), expected, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn uniquify_cases() {
+        let dir = std::env::temp_dir().join(format!("mget-test-{}-uniquify", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        std::fs::write(dir.join("a.1.txt"), "").unwrap();
+        std::fs::write(dir.join("noext"), "").unwrap();
+        let none = |_: &Path| false;
+        // path, taken by another download, expected
+        let cases = [
+            ("free.txt", vec![], "free.txt"),
+            ("a.txt", vec![], "a.2.txt"),
+            ("a.txt", vec!["a.2.txt"], "a.3.txt"),
+            ("free.txt", vec!["free.txt"], "free.1.txt"),
+            ("noext", vec![], "noext.1"),
+            ("a.tar.gz", vec![], "a.tar.gz"),
+        ];
+        for (name, taken, expected) in cases {
+            let taken: Vec<PathBuf> = taken.iter().map(|name| dir.join(name)).collect();
+            let unique = uniquify(&dir.join(name), |path| taken.iter().any(|t| t == path));
+            assert_eq!(unique, dir.join(expected), "{}", name);
+        }
+        // a dot in the directory is no extension
+        let dotted = dir.join("v1.2");
+        std::fs::create_dir_all(&dotted).unwrap();
+        std::fs::write(dotted.join("notes"), "").unwrap();
+        assert_eq!(
+            uniquify(&dotted.join("notes"), none),
+            dotted.join("notes.1")
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn windows_filename_cases() {
+        let cases = [
+            ("a:b?c=1", "a_b_c=1"),
+            (r#"<x>|"y"*.txt"#, "_x___y__.txt"),
+            ("name. . ", "name"),
+            ("nul.txt", "_nul.txt"),
+            ("COM1", "_COM1"),
+            ("aux .tar.gz", "_aux .tar.gz"),
+            ("console.log", "console.log"),
+            ("com10", "com10"),
+            ("plain.txt", "plain.txt"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(windows_filename(name), expected, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn long_paths_get_the_extended_prefix() {
+        let long = format!(r"C:\downloads\{}.bin", "a".repeat(250));
+        assert_eq!(extended_path(&long), Some(format!(r"\\?\{}", long)));
+        assert_eq!(extended_path(r"C:\downloads\short.bin"), None);
+        let share = format!(r"\\server\share\{}", "b".repeat(250));
+        assert_eq!(
+            extended_path(&share),
+            Some(format!(r"\\?\UNC\{}", &share[2..]))
+        );
+        // MAX_PATH counts utf-16 units and the nul
+        let exact = format!(r"C:\{}", "c".repeat(MAX_PATH - 4));
+        assert_eq!(extended_path(&exact), None);
+        assert!(extended_path(&format!("{}c", exact)).is_some());
+        if !cfg!(windows) {
+            assert_eq!(long_path(long.clone()), long);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    netrc::Netrc,
+    remote::RemoteFile,
+    worker::{forward_body, Task, TaskContext, TaskResult},
+};
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use std::{
+    io::{Error, ErrorKind},
+    sync::{mpsc::SyncSender, Arc},
+};
+use suppaftp::{native_tls::TlsConnector, types::FileType, FtpError, NativeTlsConnector};
+
+type FtpStream = suppaftp::NativeTlsFtpStream;
+
+#[derive(Clone)]
+pub(crate) struct FtpOptions {
+    // anonymous unless given here or in the url
+    pub(crate) login: Option<(String, String)>,
+    // looked up by host before falling back to anonymous
+    pub(crate) netrc: Option<Arc<Netrc>>,
+    // skip certificate verification for ftps
+    pub(crate) insecure: bool,
+}
+
+// a closed connection or a 4xx reply is transient, anything else won't get better by retrying
+fn ftp_error(e: FtpError) -> Error {
+    match e {
+        FtpError::ConnectionError(e) if e.kind() == ErrorKind::TimedOut => {
+            Error::new(ErrorKind::TimedOut, e)
+        }
+        FtpError::ConnectionError(e) => Error::new(ErrorKind::ConnectionReset, e),
+        FtpError::UnexpectedResponse(response) if (400..500).contains(&response.status.code()) => {
+            Error::new(
+                ErrorKind::ConnectionReset,
+                format!("FTP error: {}", response),
+            )
+        }
+        e => Error::new(ErrorKind::InvalidData, format!("FTP error: {}", e)),
+    }
+}
+
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+
+// the path is relative to the login directory, as in RFC 1738
+fn remote_path(url: &Url) -> String {
+    decode(url.path().strip_prefix('/').unwrap_or(url.path()))
+}
+
+// log in and switch to binary mode, upgrading to TLS first for ftps
+fn connect(url: &Url, options: &FtpOptions) -> Result<FtpStream, Error> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "FTP url without a host"))?;
+    let mut ftp = FtpStream::connect((host, url.port().unwrap_or(21))).map_err(ftp_error)?;
+    if url.scheme() == "ftps" {
+        let connector = TlsConnector::builder()
+            .danger_accept_invalid_certs(options.insecure)
+            .build()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        ftp = ftp
+            .into_secure(NativeTlsConnector::from(connector), host)
+            .map_err(ftp_error)?;
+    }
+    let (user, password) = match (&options.login, url.username()) {
+        (Some((user, password)), _) => (user.clone(), password.clone()),
+        (None, "") => match options
+            .netrc
+            .as_ref()
+            .and_then(|netrc| netrc.login(url.host_str()?))
+        {
+            Some((user, password)) => (user.to_string(), password.to_string()),
+            None => ("anonymous".to_string(), "mget@".to_string()),
+        },
+        (None, user) => (decode(user), decode(url.password().unwrap_or_default())),
+    };
+    ftp.login(user, password).map_err(ftp_error)?;
+    ftp.transfer_type(FileType::Binary).map_err(ftp_error)?;
+    Ok(ftp)
+}
+
+// SIZE for the length, and a REST that the server accepts means ranges can be read
+pub(crate) fn get_file_size(url: &Url, options: &FtpOptions) -> Result<RemoteFile, Error> {
+    let mut ftp = connect(url, options)?;
+    let path = remote_path(url);
+    let size = ftp.size(&path).map_err(ftp_error)? as u64;
+    let accept_ranges = ftp.resume_transfer(1).is_ok() && ftp.resume_transfer(0).is_ok();
+    let _ = ftp.quit();
+    Ok(RemoteFile {
+        url: url.to_string(),
+        size: Some(size),
+        accept_ranges,
+        file_name: None,
+        content_type: None,
+        validator: None,
+        checksums: Vec::new(),
+        version: None,
+    })
+}
+
+// REST to `pos` and RETR, reading until `end` since the server sends the rest of the file
+pub(crate) fn fetch_range(
+    ctx: &TaskContext,
+    options: &FtpOptions,
+    tx: &SyncSender<TaskResult>,
+    task: &Task,
+    pos: &mut u64,
+    end: u64,
+) -> Result<(), Error> {
+    let url = Url::parse(&task.url).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let mut ftp = connect(&url, options)?;
+    if task.ranged && *pos + ctx.offset > 0 {
+        ftp.resume_transfer((*pos + ctx.offset) as usize)
+            .map_err(ftp_error)?;
+    }
+    let mut stream = ftp.retr_as_stream(remote_path(&url)).map_err(ftp_error)?;
+    forward_body(ctx, tx, task, &mut stream, pos, end)?;
+    // a ranged or limited read stops early, which the server reports as an aborted transfer
+    match task.ranged || ctx.limit.is_some() {
+        true => drop(stream),
+        false => stream.finish().map_err(ftp_error)?,
+    }
+    let _ = ftp.quit();
+    Ok(())
+}
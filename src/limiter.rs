@@ -0,0 +1,52 @@
+use crate::clock::Clock;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// a token bucket shared by all workers, capping their aggregate speed
+pub(crate) struct RateLimiter {
+    // bytes per second
+    rate: f64,
+    // allow bursts of up to 100ms worth of bytes
+    capacity: f64,
+    // available tokens, negative after a read larger than the bucket, and the last refill
+    state: Mutex<(f64, Instant)>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u64, clock: Arc<dyn Clock>) -> Self {
+        let rate = std::cmp::max(rate, 1) as f64;
+        let capacity = rate / 10.0;
+        RateLimiter {
+            rate,
+            capacity,
+            state: Mutex::new((capacity, clock.now())),
+            clock,
+        }
+    }
+
+    // block until the bucket has tokens left
+    pub(crate) fn wait(&self) {
+        loop {
+            let delay = {
+                let mut state = self.state.lock().unwrap();
+                let now = self.clock.now();
+                let refill = now.duration_since(state.1).as_secs_f64() * self.rate;
+                *state = (f64::min(state.0 + refill, self.capacity), now);
+                if state.0 > 0.0 {
+                    return;
+                }
+                Duration::from_secs_f64(-state.0 / self.rate)
+            };
+            self.clock
+                .sleep(std::cmp::max(delay, Duration::from_millis(1)));
+        }
+    }
+
+    // account for bytes that were read
+    pub(crate) fn consume(&self, bytes: usize) {
+        self.state.lock().unwrap().0 -= bytes as f64;
+    }
+}
@@ -1,230 +1,1180 @@
-use clap::{command, Parser};
-use reqwest::Url;
+use clap::Parser;
+use env_logger::Target;
+use log::{Level, LevelFilter};
+use mget_rs::{
+    ContentTypeCheck, Downloader, Existing, IpFamily, MgetError, Probe, ProgressStyle, Report,
+    Transfer,
+};
+use serde_json::json;
 use std::{
-    io::{Error, ErrorKind, Read, Seek, Write},
-    sync::mpsc::Sender,
-    thread::spawn,
+    fmt::Display,
+    io::ErrorKind,
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
-enum TaskResult {
-    Downloading(usize, u64, Box<[u8]>),
-    Failed(usize, Error),
-    Done(usize),
-}
+const EXIT_CODES: &str = "Exit codes:
+  0    every url was downloaded
+  1    invalid arguments or another error
+  2    network or connection error
+  3    HTTP 4xx response
+  4    HTTP 5xx response
+  5    checksum mismatch
+  6    filesystem error
+  7    the --on-complete command failed
+  130  interrupted
+With several urls the code of the first failure is used.";
 
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(version, after_help = EXIT_CODES)]
 struct Cli {
+    /// Parallel connections, 0 picks one per CPU up to 16
     #[clap(long, short, default_value = "2")]
     threads: usize,
 
+    /// Start with 2 threads and add more while the speed keeps rising, up to --threads
+    #[clap(long)]
+    adaptive_threads: bool,
+
+    /// Urls downloaded at the same time, each over up to --threads connections, so a server may
+    /// see max-concurrent x threads connections at once; always one at a time with --output -
+    #[clap(
+        long,
+        default_value = "1",
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    max_concurrent: u16,
+
     #[clap(long, short)]
     output: Option<String>,
 
+    /// Output path per url with {host}, {filename}, {ext}, {index} and {date} replaced, e.g.
+    /// {host}/{filename}
+    #[clap(long, conflicts_with = "output")]
+    output_template: Option<String>,
+
+    /// Where the ETags of finished downloads are kept, so an unchanged file isn't downloaded
+    /// again [default: ~/.cache/mget]
+    #[clap(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Always download, without asking the server whether the saved file changed
+    #[clap(long, conflicts_with = "cache_dir")]
+    no_cache: bool,
+
+    /// Name for a url without a file name in its path, e.g. https://example.com/ [default: the
+    /// host and date, like example.com-20240101.html]
+    #[clap(long, value_name = "NAME")]
+    default_name: Option<String>,
+
+    /// Name the file after the url redirects lead to, unless the server suggests a name
+    #[clap(long)]
+    rename_from_redirect: bool,
+
+    /// Save into this directory, created when missing. An absolute --output ignores it
+    #[clap(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Create the missing directories of --output instead of failing
+    #[clap(long)]
+    create_dirs: bool,
+
+    /// Print the details of each download, RUST_LOG=debug does the same and RUST_LOG picks the
+    /// levels of the messages in general
     #[clap(long, short, default_value = "false")]
     verbose: bool,
 
-    url: String,
-}
-
-fn get_file_size(url: &str) -> Result<u64, Error> {
-    let response = reqwest::blocking::Client::new()
-        .head(url)
-        .header(reqwest::header::USER_AGENT, "curl/7.81.0")
-        .send()
-        .map_err(|e| Error::new(ErrorKind::ConnectionReset, e))?;
-
-    if !response.status().is_success() {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("Failed to get content-length: {}", response.status()),
-        ));
-    }
-    response
-        .headers()
-        .get(reqwest::header::CONTENT_LENGTH)
-        .and_then(|value| value.to_str().ok())
-        .and_then(|value| value.parse().ok())
-        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Failed to parse content-length"))
-}
-
-fn download_part(tx: Sender<TaskResult>, url: String, idx: usize, pos: u64, length: u64) -> u64 {
-    match download_part_inner(tx.clone(), url, idx, pos, length) {
-        Ok(pos) => {
-            tx.send(TaskResult::Done(idx)).ok();
-            pos
-        }
-        Err(e) => {
-            tx.send(TaskResult::Failed(idx, e)).ok();
-            0
-        }
-    }
-}
-
-fn download_part_inner(
-    tx: Sender<TaskResult>,
-    url: String,
-    idx: usize,
-    pos: u64,
-    length: u64,
-) -> Result<u64, Error> {
-    let client = reqwest::blocking::Client::new();
-    let mut response = client
-        .get(url)
-        .header(reqwest::header::USER_AGENT, "curl/7.81.0")
-        .header(
-            reqwest::header::RANGE,
-            format!("bytes={}-{}", pos, pos + length - 1),
-        )
-        .send()
-        .map_err(|e| Error::new(ErrorKind::ConnectionReset, e))?;
+    /// Print nothing but errors, the exit status tells whether the download succeeded
+    #[clap(long, short, conflicts_with = "verbose")]
+    quiet: bool,
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let reason = response.text().unwrap_or(format!("{}", status));
-        return Err(Error::new(ErrorKind::InvalidData, reason));
-    }
+    /// How to show progress: bar, log (a timestamped line per interval) or none [default: a bar
+    /// with --verbose on a terminal, log lines when redirected]
+    #[clap(long, value_name = "STYLE", value_parser = parse_progress, conflicts_with = "quiet")]
+    progress: Option<ProgressStyle>,
+
+    /// Same as --progress=none, keeping the other --verbose messages
+    #[clap(long, conflicts_with = "progress")]
+    no_progress: bool,
+
+    /// Seconds between the lines of --progress=log
+    #[clap(long, value_name = "SECS", default_value = "5")]
+    progress_interval: u64,
+
+    /// Print a summary of each download: speeds, bytes per thread, retries and mirrors
+    #[clap(long)]
+    report: bool,
+
+    /// Run a command after each successful download, with {} replaced by the file and the
+    /// MGET_FILE variable set to it. It runs without a shell, split on whitespace
+    #[clap(long, value_name = "COMMAND")]
+    on_complete: Option<String>,
+
+    /// Run the --on-complete command through sh -c, or cmd /C on Windows
+    #[clap(long, requires = "on_complete")]
+    shell: bool,
+
+    /// Print one JSON object per url to stdout instead of the messages, progress goes to stderr
+    #[clap(long, conflicts_with = "quiet")]
+    json: bool,
+
+    /// Resume a partially downloaded file
+    #[clap(long = "continue", short = 'c', default_value = "false")]
+    resume: bool,
+
+    /// Before resuming, download the last 64k of the partial file again and start over if they
+    /// differ
+    #[clap(long, requires = "resume")]
+    verify_tail: bool,
+
+    /// Sync the file and the resume state to disk after every chunk, to survive a power loss
+    #[clap(long)]
+    fsync: bool,
+
+    /// Sync the file to disk, and record the resume state, every time this many more bytes were
+    /// written; small values slow down fast downloads
+    #[clap(long, value_name = "BYTES", value_parser = parse_size)]
+    sync_every: Option<u64>,
+
+    /// Wait for the disk on every write (O_DSYNC, FILE_FLAG_WRITE_THROUGH on windows) to see disk
+    /// errors at once; much slower on most disks
+    #[clap(long)]
+    no_buffer: bool,
+
+    /// Offer HTTP/2 to https servers, multiplexing all threads over one connection
+    #[clap(long)]
+    http2: bool,
+
+    /// Connect from this local IP address, or bind to this interface name (Linux and macOS)
+    #[clap(long, value_name = "IP_OR_NAME")]
+    interface: Option<String>,
+
+    /// Only connect over IPv4, fails for a host without an IPv4 address
+    #[clap(long, short = '4', conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only connect over IPv6, fails for a host without an IPv6 address
+    #[clap(long, short = '6')]
+    ipv6: bool,
+
+    /// Connect to ADDR, or any of a comma separated list, instead of resolving HOST, for every
+    /// port despite the PORT; may be repeated
+    #[clap(long, value_name = "HOST:PORT:ADDR", value_parser = parse_resolve)]
+    resolve: Vec<(String, Vec<IpAddr>)>,
+
+    /// Fail up front when the output filesystem hasn't room for the file
+    #[clap(long)]
+    check_space: bool,
+
+    /// Leave runs of zeros as holes in the file, on filesystems supporting sparse files
+    #[clap(long)]
+    sparse: bool,
+
+    /// Save the html page of a url ending in /, usually refused as a directory listing
+    #[clap(long)]
+    force_html: bool,
+
+    /// Warn when the response's content type doesn't fit the extension of the output file
+    #[clap(long)]
+    check_content_type: bool,
+
+    /// Fail instead of warning on a --check-content-type mismatch
+    #[clap(long, requires = "check_content_type")]
+    strict: bool,
+
+    /// Overwrite an existing output file instead of saving to a numbered name
+    #[clap(
+        long,
+        short,
+        visible_alias = "overwrite",
+        conflicts_with = "no_clobber"
+    )]
+    force: bool,
+
+    /// Fail instead of saving to a numbered name when the output file exists
+    #[clap(long)]
+    no_clobber: bool,
+
+    /// Retries of a failed range request on connection errors and --retry-on statuses, on the
+    /// same connection and from the last received byte
+    #[clap(long, default_value = "3")]
+    retries: usize,
+
+    /// Times a chunk that ran out of retries is handed to a new connection, over the whole
+    /// download. Each new connection gets --retries again, retries don't use up restarts
+    #[clap(long, visible_alias = "reconnect", default_value = "3")]
+    max_thread_restarts: usize,
+
+    /// Response statuses worth a retry, a comma list of codes and classes like 4xx, others fail
+    /// at once [default: 5xx,408,429]
+    #[clap(long, value_name = "CODES", value_parser = parse_statuses)]
+    retry_on: Option<Statuses>,
+
+    /// Delay in milliseconds before the first retry, doubled on each following one up to a minute
+    #[clap(long, default_value = "500")]
+    retry_wait: u64,
+
+    /// Timeout in seconds for establishing a connection [default: no timeout]
+    #[clap(long)]
+    connect_timeout: Option<u64>,
+
+    /// Maximum time in seconds a single download may take [default: no limit]
+    #[clap(long)]
+    max_time: Option<u64>,
+
+    /// Abort a download slower than this many bytes per second for --speed-time seconds
+    #[clap(long, value_name = "BYTES", value_parser = parse_size)]
+    speed_limit: Option<u64>,
+
+    /// Seconds the speed may stay below --speed-limit [default: 30]
+    #[clap(long, value_name = "SECS", requires = "speed_limit")]
+    speed_time: Option<u64>,
+
+    /// Timeout in seconds waiting for response data [default: no timeout]
+    #[clap(long)]
+    read_timeout: Option<u64>,
+
+    /// Size of the chunks the threads take turns downloading, accepts suffixes like 512k or 8M
+    #[clap(long, default_value = "4M", value_parser = parse_size)]
+    chunk_size: u64,
+
+    /// Use fewer threads when each would download less than this, 0 keeps all of them
+    #[clap(long, default_value = "1M", value_parser = parse_size)]
+    min_split_size: u64,
+
+    /// Bytes read from the network at once, accepts suffixes like 16k. Memory use grows with
+    /// buffer size x (threads + 64 queued reads)
+    #[clap(long, default_value = "64k", value_parser = parse_size)]
+    buffer_size: u64,
+
+    /// Limit the total download speed in bytes per second, accepts suffixes like 500k or 1M
+    #[clap(long, value_parser = parse_size)]
+    limit_rate: Option<u64>,
+
+    /// Download only the first bytes of the file, accepts suffixes like 10M
+    #[clap(long, value_parser = parse_size)]
+    max_bytes: Option<u64>,
+
+    /// Refuse larger files and stop a download of unknown size past this, accepts suffixes
+    /// like 2G
+    #[clap(long, value_name = "BYTES", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Only download the inclusive start-end byte ranges listed in this file, one per line, into
+    /// the existing output file
+    #[clap(long, value_name = "PATH", requires = "output")]
+    ranges_file: Option<String>,
+
+    /// Start at this byte of the remote file, saving the rest from the start of the output
+    #[clap(long, value_parser = parse_size, default_value = "0")]
+    start_offset: u64,
+
+    /// Verify the downloaded file against a SHA-256 in hex
+    #[clap(long)]
+    sha256: Option<String>,
+
+    /// Verify the downloaded file against an MD5 in hex
+    #[clap(long)]
+    md5: Option<String>,
+
+    /// User name for HTTP Basic authentication or the FTP login instead of anonymous, prompts for
+    /// the password when --password is not given
+    #[clap(long, conflicts_with = "bearer")]
+    user: Option<String>,
 
-    let mut pos = pos;
-    loop {
-        let mut buffer = [0u8; 8 * 1024];
-        let n = response.read(&mut buffer)?;
-        if n == 0 {
-            return Ok(pos);
+    /// Password for HTTP Basic authentication or the FTP login
+    #[clap(long, requires = "user")]
+    password: Option<String>,
+
+    /// Token for `Authorization: Bearer` authentication
+    #[clap(long)]
+    bearer: Option<String>,
+
+    /// Log in with the netrc entry of the host, from FILE or else $NETRC or ~/.netrc, which are
+    /// read anyway when they exist; FILE must be chmod 600, the default is skipped with a warning
+    /// otherwise. --user, --bearer and -H Authorization win
+    #[clap(long, value_name = "FILE", num_args = 0..=1)]
+    netrc: Option<Option<PathBuf>>,
+
+    /// Don't read $NETRC or ~/.netrc
+    #[clap(long, conflicts_with = "netrc")]
+    no_netrc: bool,
+
+    /// User-Agent sent with every request [default: mget/<version>]
+    #[clap(long, short = 'A')]
+    user_agent: Option<String>,
+
+    /// Referer sent with every request
+    #[clap(long, value_name = "URL")]
+    referer: Option<String>,
+
+    /// Cookie as "name=value" sent with every request, may be repeated
+    #[clap(long = "cookie", short = 'b', value_name = "COOKIE")]
+    cookies: Vec<String>,
+
+    /// Netscape format cookie file to load cookies from and save them to, created if missing
+    #[clap(long, value_name = "PATH")]
+    cookie_jar: Option<PathBuf>,
+
+    /// Extra request header as "Name: Value", may be repeated
+    #[clap(long = "header", short = 'H', value_name = "HEADER", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// Request a gzip, brotli or deflate encoded body and decode it. Ranges don't
+    /// map onto the decoded file, so this downloads with a single thread, can't
+    /// resume and treats Content-Length as advisory
+    #[clap(long)]
+    compressed: bool,
+
+    /// Proxy url with an http, https or socks5 scheme, may contain user:pass@.
+    /// Overrides the HTTP_PROXY, HTTPS_PROXY and ALL_PROXY environment variables
+    #[clap(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Skip TLS certificate verification, like curl -k
+    #[clap(long, short = 'k')]
+    insecure: bool,
+
+    /// PEM file with root certificates to trust in addition to the system ones
+    #[clap(long, value_name = "PATH")]
+    cacert: Option<String>,
+
+    /// Maximum number of redirects to follow
+    #[clap(long, default_value = "10")]
+    max_redirects: usize,
+
+    /// Schemes urls may use, like curl: e.g. =https for https only, or -ftp,-ftps
+    #[clap(long, value_name = "PROTOCOLS", allow_hyphen_values = true)]
+    proto: Option<String>,
+
+    /// Schemes redirects may lead to, in the same syntax [default: any but https to http]
+    #[clap(long, value_name = "PROTOCOLS", allow_hyphen_values = true)]
+    proto_redir: Option<String>,
+
+    /// Read urls from a file, one per line, or from stdin with "-"
+    #[clap(long, short = 'i', value_name = "PATH")]
+    input_file: Option<String>,
+
+    /// Only probe the urls and print what a download would get, without downloading
+    #[clap(long)]
+    head: bool,
+
+    /// Another url of the same file to fetch chunks from, may be repeated
+    #[clap(long, value_name = "URL")]
+    mirror: Vec<String>,
+
+    /// Treat the urls as mirrors of one file and spread its chunks over them
+    #[clap(long, conflicts_with = "head")]
+    spread_mirrors: bool,
+
+    /// Download the file described by a Metalink document, a path or an url
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["urls", "input_file", "mirror", "spread_mirrors", "head"]
+    )]
+    metalink: Option<String>,
+
+    #[clap(required_unless_present_any = ["input_file", "metalink"])]
+    urls: Vec<String>,
+}
+
+// parse a byte count with an optional k/M/G suffix, in powers of 1024
+fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_alphabetic() => (&value[..idx], c.to_ascii_lowercase()),
+        _ => (value, 'b'),
+    };
+    let scale: u64 = match unit {
+        'b' => 1,
+        'k' => 1024,
+        'm' => 1024 * 1024,
+        'g' => 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown size suffix: {}", unit)),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(scale))
+        .ok_or_else(|| format!("invalid size: {}", value))
+}
+
+#[derive(Clone, Debug)]
+struct Statuses(Vec<u16>);
+
+// `5xx,429,408`, a class stands for its hundred codes
+fn parse_statuses(value: &str) -> Result<Statuses, String> {
+    let mut statuses = Vec::new();
+    for item in value.split(',').map(str::trim) {
+        let class = item
+            .strip_suffix("xx")
+            .or_else(|| item.strip_suffix("XX"))
+            .and_then(|class| class.parse::<u16>().ok())
+            .filter(|class| (1..=5).contains(class));
+        match (class, item.parse::<u16>()) {
+            (Some(class), _) => statuses.extend(class * 100..class * 100 + 100),
+            (None, Ok(code)) if (100..600).contains(&code) => statuses.push(code),
+            _ => return Err(format!("expected a status like 503 or 5xx, got {:?}", item)),
         }
+    }
+    Ok(Statuses(statuses))
+}
 
-        tx.send(TaskResult::Downloading(
-            idx,
-            pos,
-            buffer[..n].to_vec().into_boxed_slice(),
-        ))
-        .map_err(|_| Error::new(ErrorKind::InvalidData, "Failed to send download event"))?;
-        pos += n as u64;
+fn parse_progress(value: &str) -> Result<ProgressStyle, String> {
+    match value {
+        "bar" => Ok(ProgressStyle::Bar),
+        "log" => Ok(ProgressStyle::Log),
+        "none" => Ok(ProgressStyle::Off),
+        _ => Err(format!("expected bar, log or none, got {:?}", value)),
     }
 }
 
-fn download(
-    url: &str,
-    output: Option<String>,
-    threads: usize,
-    verbose: bool,
-) -> Result<String, Error> {
-    let file_name = match output {
-        Some(name) => name.to_string(),
-        None => {
-            let url = Url::parse(url).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
-            url.path_segments()
-                .and_then(|segments| segments.last())
-                .and_then(|name| if name.is_empty() { None } else { Some(name) })
-                .unwrap_or("index.html")
-                .to_string()
-        }
+// split "Name: Value", the name and value themselves are validated by the builder
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+        None => Err(format!("expected \"Name: Value\", got {:?}", value)),
+    }
+}
+
+// curl's `host:port:addr[,addr]`, IPv6 addresses with or without brackets
+fn parse_resolve(value: &str) -> Result<(String, Vec<IpAddr>), String> {
+    let mut parts = value.splitn(3, ':');
+    let (Some(host), Some(port), Some(addrs)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("expected HOST:PORT:ADDR, got {:?}", value));
     };
+    if host.is_empty() || port.parse::<u16>().is_err() {
+        return Err(format!("expected HOST:PORT:ADDR, got {:?}", value));
+    }
+    let addrs = addrs
+        .split(',')
+        .map(|addr| {
+            let addr = addr.trim();
+            let bare = addr
+                .strip_prefix('[')
+                .and_then(|addr| addr.strip_suffix(']'));
+            bare.unwrap_or(addr)
+                .parse()
+                .map_err(|_| format!("invalid IP address {:?}", addr))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((host.to_string(), addrs))
+}
 
-    let file_size = match get_file_size(url)? {
-        0 => return Err(Error::new(ErrorKind::InvalidData, "File size is 0")),
-        file_size => file_size,
+// one url per line, blank lines and # comments are skipped
+fn read_urls(path: &str) -> std::io::Result<Vec<String>> {
+    let content = match path {
+        "-" => std::io::read_to_string(std::io::stdin())?,
+        path => std::fs::read_to_string(path)?,
     };
-    // try rename the file to avoid conflict
-    let mut file_name = file_name;
-    let mut index = 1;
-    while std::fs::metadata(&file_name).is_ok() {
-        let parts: Vec<&str> = file_name.rsplitn(2, '.').collect();
-        if parts.len() == 2 {
-            file_name = format!("{}.{}.{}", parts[1], index, parts[0]);
-        } else {
-            file_name = format!("{}.{}", file_name, index);
-        }
-        index += 1;
-    }
-    if verbose {
-        println!(
-            "Downloading {} to {} with {} threads, content-length: {}",
-            url, file_name, threads, file_size
-        );
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+// inclusive `start-end` ranges like those of a Range header, one per line, blank lines and
+// # comments are skipped
+fn read_ranges(path: &str) -> std::io::Result<Vec<std::ops::Range<u64>>> {
+    let mut ranges = Vec::new();
+    for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let range = line
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.trim().parse().ok()?, end.trim().parse().ok()?)))
+            .filter(|(start, end): &(u64, u64)| start <= end);
+        match range {
+            Some((start, end)) => ranges.push(start..end + 1),
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("line {}: expected start-end, got {:?}", number + 1, line),
+                ))
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+// 1024-based units, like the size suffixes
+fn format_size(bytes: u64) -> String {
+    let units = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} bytes", bytes),
+        unit => format!("{:.2} {} ({} bytes)", size, units[unit], bytes),
     }
+}
 
-    let threads = std::cmp::max(threads, 1);
-    let (tx, rx) = std::sync::mpsc::channel::<TaskResult>();
-    let mut done_count = 0;
+fn print_probe(probe: &Probe) {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    println!("URL: {}", probe.url);
+    println!("File: {}", probe.file_name);
+    println!(
+        "Size: {}",
+        probe.size.map_or("unknown".to_string(), format_size)
+    );
+    println!("Ranges: {}", yes_no(probe.accept_ranges));
+    println!(
+        "Content-Type: {}",
+        probe.content_type.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "Validator: {}",
+        probe.validator.as_deref().unwrap_or("none")
+    );
+}
 
-    for idx in 0..threads {
-        let pos = idx as u64 * file_size / threads as u64;
-        let length = if idx == threads - 1 {
-            file_size - pos
-        } else {
-            file_size / threads as u64
-        };
-        let url = url.to_string();
-        let tx = tx.clone();
-        if verbose {
-            println!("Thread {} start: pos={} length={}", idx, pos, length);
-        }
-        spawn(move || download_part(tx.clone(), url, idx, pos, length));
-    }
-
-    let start_time = std::time::Instant::now();
-    let mut downloaded = 0;
-
-    let mut outfile = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(&file_name)
-        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
-
-    loop {
-        match rx.recv() {
-            Ok(TaskResult::Downloading(_idx, pos, data)) => {
-                downloaded += data.len() as u64;
-                if verbose {
-                    let percent = 100 * (downloaded / file_size);
-                    let filled_length = 50 * (downloaded / file_size);
-                    let bar = "█".repeat(filled_length as usize)
-                        + &"-".repeat((50 - filled_length) as usize);
-                    print!("\rProgress: |{}| {}% Complete", bar, percent);
-                    std::io::stdout().flush().ok();
-                    if downloaded == file_size {
-                        println!();
-                    }
+// the largest thread share over the mean one, 1.0 when the chunks were spread evenly
+fn imbalance(threads: &[u64]) -> f64 {
+    let busy: Vec<u64> = threads.iter().copied().filter(|&bytes| bytes > 0).collect();
+    let total: u64 = busy.iter().sum();
+    match busy.iter().max() {
+        Some(&max) if total > 0 => max as f64 * busy.len() as f64 / total as f64,
+        _ => 1.0,
+    }
+}
+
+fn report_lines(report: &Report) -> Vec<String> {
+    let Some(transfer) = &report.transfer else {
+        return vec![format!(
+            "Report: nothing downloaded, {} was up to date",
+            report.path.display()
+        )];
+    };
+    let mb = |speed: f64| speed / 1024.0 / 1024.0;
+    let secs = transfer.elapsed.as_secs_f64();
+    let mut lines = vec![
+        format!(
+            "Report: {} bytes in {:.2} s, {} of them downloaded",
+            report.bytes, secs, transfer.downloaded
+        ),
+        format!(
+            "  Speed: {:.2} MB/s average, {:.2} MB/s peak",
+            mb(transfer.downloaded as f64 / secs),
+            mb(transfer.peak_speed)
+        ),
+        format!(
+            "  Threads: {} (imbalance {:.2})",
+            transfer
+                .threads
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            imbalance(&transfer.threads)
+        ),
+        format!(
+            "  CRC-32: {}",
+            transfer
+                .thread_crcs
+                .iter()
+                .map(|crc| format!("{:08x}", crc))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        format!(
+            "  Retries: {}, restarted chunks: {}",
+            transfer.retries, transfer.restarts
+        ),
+    ];
+    if transfer.sources.len() > 1 {
+        for (url, bytes) in &transfer.sources {
+            lines.push(format!("  Source: {} {} bytes", url, bytes));
+        }
+    }
+    lines
+}
+
+fn report_json(transfer: &Transfer) -> serde_json::Value {
+    json!({
+        "downloaded": transfer.downloaded,
+        "elapsed_secs": transfer.elapsed.as_secs_f64(),
+        "avg_speed": transfer.downloaded as f64 / transfer.elapsed.as_secs_f64(),
+        "peak_speed": transfer.peak_speed,
+        "threads": transfer.threads,
+        "imbalance": imbalance(&transfer.threads),
+        "thread_crcs": transfer
+            .thread_crcs
+            .iter()
+            .map(|crc| format!("{:08x}", crc))
+            .collect::<Vec<_>>(),
+        "retries": transfer.retries,
+        "restarts": transfer.restarts,
+        "sources": transfer
+            .sources
+            .iter()
+            .map(|(url, bytes)| json!({"url": url, "bytes": bytes}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+// how the outcome of each download is printed
+#[derive(Clone, Copy)]
+struct Printing {
+    json: bool,
+    report: bool,
+    quiet: bool,
+    to_stdout: bool,
+}
+
+// the json object, report and outcome of one download
+fn print_result(args: Printing, url: &str, result: &Result<Report, MgetError>) {
+    if args.json {
+        let object = match result {
+            Ok(report) => {
+                let mut object = json!({
+                    "url": url,
+                    "file": report.path.display().to_string(),
+                    "bytes": report.bytes,
+                    "elapsed_secs": report.elapsed.as_secs_f64(),
+                    "avg_speed": report.bytes as f64 / report.elapsed.as_secs_f64(),
+                    "status": "ok",
+                });
+                if args.report {
+                    object["report"] = report.transfer.as_ref().map(report_json).into();
                 }
-                outfile.seek(std::io::SeekFrom::Start(pos))?;
-                outfile.write_all(&data)?;
-            }
-            Ok(TaskResult::Failed(idx, e)) => {
-                println!("Thread {} failed: {}", idx, e);
-                return Err(e);
+                object
             }
-            Ok(TaskResult::Done(_idx)) => {
-                done_count += 1;
-                if done_count == threads {
-                    break;
-                }
+            Err(e) => json!({"url": url, "status": "error", "message": e.to_string()}),
+        };
+        println!("{}", object);
+    }
+    if let (true, false, Ok(report)) = (args.report, args.json, result) {
+        for line in report_lines(report) {
+            match args.to_stdout {
+                true => eprintln!("{}", line),
+                false => println!("{}", line),
             }
-            Err(e) => {
-                return Err(Error::new(ErrorKind::InvalidData, e));
+        }
+    }
+    match result {
+        Ok(_) if args.quiet || args.json => {}
+        // keep stdout clean when the file itself was written there
+        Ok(report) if report.path.as_os_str() == "-" => eprintln!("Downloaded successfully"),
+        Ok(report) => println!("Downloaded successfully: {}", report.path.display()),
+        Err(e) if e.kind() == ErrorKind::Interrupted && !args.json => eprintln!("\n{}", e),
+        Err(_) if args.json => {}
+        Err(e) => eprintln!("Error: {}: {}", url, e),
+    }
+}
+
+// see EXIT_CODES
+fn exit_code(e: &MgetError) -> i32 {
+    match e {
+        MgetError::Network(_) => 2,
+        MgetError::Http { status, .. } if status.is_client_error() => 3,
+        MgetError::Http { status, .. } if status.is_server_error() => 4,
+        MgetError::ChecksumMismatch { .. } => 5,
+        MgetError::Io(e) => match e.kind() {
+            ErrorKind::Interrupted => 130,
+            // broken connections and FTP
+            ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::TimedOut
+            | ErrorKind::UnexpectedEof => 2,
+            // the os only reports errors of local files, the network ones are wrapped
+            _ if e.raw_os_error().is_some() => 6,
+            ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::AlreadyExists
+            | ErrorKind::StorageFull => 6,
+            _ => 1,
+        },
+        _ => 1,
+    }
+}
+
+// the --on-complete command for a downloaded `path`
+fn run_hook(command: &str, shell: bool, path: &Path) -> Result<(), String> {
+    let mut hook = match (shell, cfg!(windows)) {
+        // the path is only expanded by the shell, so a name can't inject commands
+        (true, false) => {
+            let mut hook = Command::new("sh");
+            hook.arg("-c").arg(command.replace("{}", "\"$MGET_FILE\""));
+            hook
+        }
+        (true, true) => {
+            let mut hook = Command::new("cmd");
+            hook.arg("/C").arg(command.replace("{}", "\"%MGET_FILE%\""));
+            hook
+        }
+        (false, _) => {
+            let mut words = command.split_whitespace();
+            let program = words.next().ok_or("The --on-complete command is empty")?;
+            let mut hook = Command::new(program);
+            for word in words {
+                match word.contains("{}") {
+                    true => hook.arg(word.replace("{}", &path.to_string_lossy())),
+                    false => hook.arg(word),
+                };
             }
+            hook
         }
+    };
+    let status = hook
+        .env("MGET_FILE", path)
+        .status()
+        .map_err(|e| format!("Running {}: {}", command, e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("{} failed with {}", command, status)),
     }
+}
 
-    let elapsed = start_time.elapsed();
-    if verbose {
-        println!(
-            "Downloaded {} bytes in {} seconds, speed: {:.2} MB/s",
-            file_size,
-            elapsed.as_secs_f32(),
-            file_size as f32 / 1024.0 / 1024.0 / elapsed.as_secs_f32()
-        );
+// report an error that prevents any download and exit
+fn fail(json: bool, message: impl Display) -> ! {
+    match json {
+        true => println!(
+            "{}",
+            json!({"status": "error", "message": message.to_string()})
+        ),
+        false => eprintln!("Error: {}", message),
     }
-    outfile.flush().ok();
-    Ok(file_name)
+    std::process::exit(1);
 }
 
 // a multiple threads downloader
 // by ruzhila.cn
 fn main() {
-    let args = Cli::parse();
-    match download(&args.url, args.output.clone(), args.threads, args.verbose) {
-        Ok(filename) => println!("Downloaded successfully: {}", filename),
-        Err(e) => eprintln!("Error: {}", e),
+    // clap exits with 2 on usage errors, which is taken by network errors here
+    let args = Cli::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() { 1 } else { 0 });
+    });
+    let to_stdout = args.output.as_deref() == Some("-");
+    let printing = Printing {
+        json: args.json,
+        report: args.report,
+        quiet: args.quiet,
+        to_stdout,
+    };
+    let mut urls = args.urls;
+    urls.extend(args.metalink.clone());
+    if let Some(path) = &args.input_file {
+        match read_urls(path) {
+            Ok(lines) => urls.extend(lines),
+            Err(e) => fail(args.json, format!("{}: {}", path, e)),
+        }
+    }
+    if urls.is_empty() {
+        fail(args.json, "no urls to download");
+    }
+    let mut mirrors = args.mirror;
+    if args.spread_mirrors {
+        mirrors.extend(urls.drain(1..));
+    }
+    if !mirrors.is_empty() && urls.len() > 1 {
+        fail(args.json, "--mirror can't be used with more than one url");
+    }
+    let mirrors: Vec<&str> = mirrors.iter().map(String::as_str).collect();
+    // every download would land on the same file, only stdout can take them one after another
+    if urls.len() > 1 && args.output.is_some() && !to_stdout {
+        fail(args.json, "--output can't be used with more than one url");
+    }
+    if args.json && to_stdout {
+        fail(args.json, "--json can't be used with --output -");
+    }
+    // files written to stdout at once would interleave
+    let max_concurrent = match to_stdout {
+        true => 1,
+        false => args.max_concurrent,
+    };
+    // the first Ctrl-C stops the workers and keeps the state file, a second one exits right away
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_stop.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+    }) {
+        fail(args.json, e);
+    }
+    // the messages stay plain lines, on stderr when stdout carries the file or the json
+    let mut logger = env_logger::Builder::new();
+    logger
+        .filter_module(
+            "mget_rs",
+            match (args.quiet, args.verbose) {
+                (true, _) => LevelFilter::Error,
+                (_, true) => LevelFilter::Debug,
+                _ => LevelFilter::Info,
+            },
+        )
+        .parse_default_env()
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .target(match args.json || to_stdout {
+            true => Target::Stderr,
+            false => Target::Stdout,
+        })
+        .init();
+    let mut builder = Downloader::builder()
+        .threads(args.threads)
+        .adaptive_threads(args.adaptive_threads)
+        .chunk_size(args.chunk_size)
+        .min_split_size(args.min_split_size)
+        .buffer_size(args.buffer_size as usize)
+        .verbose(args.verbose || log::log_enabled!(target: "mget_rs", Level::Debug))
+        .stderr(args.json)
+        .quiet(args.quiet)
+        .log(true)
+        .progress_style(match args.no_progress {
+            true => ProgressStyle::Off,
+            false => args.progress.unwrap_or(ProgressStyle::Auto),
+        })
+        .progress_interval(Duration::from_secs(args.progress_interval))
+        .resume(args.resume)
+        .verify_tail(args.verify_tail)
+        .fsync(args.fsync)
+        .write_through(args.no_buffer)
+        .check_space(args.check_space)
+        .create_dirs(args.create_dirs)
+        .rename_from_redirect(args.rename_from_redirect)
+        .sparse(args.sparse)
+        .force_html(args.force_html)
+        .check_content_type(match (args.check_content_type, args.strict) {
+            (false, _) => ContentTypeCheck::Off,
+            (true, false) => ContentTypeCheck::Warn,
+            (true, true) => ContentTypeCheck::Fail,
+        })
+        .http2(args.http2)
+        .existing(match (args.force, args.no_clobber) {
+            (true, _) => Existing::Overwrite,
+            (_, true) => Existing::Fail,
+            _ => Existing::Rename,
+        })
+        .retries(args.retries)
+        .max_restarts(args.max_thread_restarts)
+        .retry_wait(Duration::from_millis(args.retry_wait))
+        .max_redirects(args.max_redirects)
+        .compressed(args.compressed)
+        .start_offset(args.start_offset)
+        .progress_label(max_concurrent > 1)
+        .stop_flag(stop.clone());
+    if let Some(output) = args.output {
+        builder = builder.output(output);
+    }
+    if let Some(dir) = args.cache_dir {
+        builder = builder.cache_dir(dir);
+    }
+    if args.no_cache {
+        builder = builder.cache(false);
+    }
+    if let Some(template) = args.output_template {
+        builder = builder.output_template(template);
+    }
+    if let Some(protocols) = args.proto {
+        builder = builder.protocols(protocols);
+    }
+    if let Some(protocols) = args.proto_redir {
+        builder = builder.redirect_protocols(protocols);
+    }
+    if let Some(name) = args.default_name {
+        builder = builder.default_name(name);
+    }
+    if let Some(dir) = args.output_dir {
+        builder = builder.output_dir(dir);
+    }
+    if let Some(rate) = args.limit_rate {
+        builder = builder.limit_rate(rate);
+    }
+    if let Some(path) = &args.ranges_file {
+        match read_ranges(path) {
+            Ok(ranges) => builder = builder.patch_ranges(ranges),
+            Err(e) => fail(args.json, format!("{}: {}", path, e)),
+        }
+    }
+    if let Some(bytes) = args.sync_every {
+        builder = builder.sync_every(bytes);
+    }
+    if let Some(bytes) = args.max_bytes {
+        builder = builder.max_bytes(bytes);
+    }
+    if let Some(bytes) = args.max_file_size {
+        builder = builder.max_file_size(bytes);
+    }
+    if let Some(hex) = args.sha256 {
+        builder = builder.sha256(hex);
+    }
+    if let Some(hex) = args.md5 {
+        builder = builder.md5(hex);
+    }
+    if let Some(user_agent) = args.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(referer) = args.referer {
+        builder = builder.referer(referer);
+    }
+    for cookie in args.cookies {
+        builder = builder.cookie(cookie);
+    }
+    if let Some(path) = args.cookie_jar {
+        builder = builder.cookie_jar(path);
+    }
+    for (name, value) in args.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(user) = args.user {
+        let password = match args.password {
+            Some(password) => password,
+            None => match rpassword::prompt_password(format!("Password for {}: ", user)) {
+                Ok(password) => password,
+                Err(e) => fail(args.json, e),
+            },
+        };
+        builder = builder.basic_auth(user, password);
+    }
+    if let Some(token) = args.bearer {
+        builder = builder.bearer_auth(token);
+    }
+    builder = match args.netrc {
+        Some(Some(path)) => builder.netrc_file(path),
+        _ => builder.netrc(!args.no_netrc),
+    };
+    if let Some(interface) = args.interface {
+        builder = builder.interface(interface);
+    }
+    for (host, addrs) in args.resolve {
+        for addr in addrs {
+            builder = builder.resolve(&host, addr);
+        }
+    }
+    if args.ipv4 {
+        builder = builder.ip_family(IpFamily::V4);
+    }
+    if args.ipv6 {
+        builder = builder.ip_family(IpFamily::V6);
+    }
+    if let Some(proxy) = args.proxy {
+        builder = builder.proxy(proxy);
+    }
+    if args.insecure {
+        eprintln!("Warning: TLS certificates are not verified, connections can be intercepted");
+        builder = builder.insecure(true);
+    }
+    if let Some(path) = args.cacert {
+        builder = builder.ca_cert(path);
+    }
+    if let Some(secs) = args.connect_timeout {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(Statuses(statuses)) = args.retry_on {
+        builder = builder.retry_on(statuses);
+    }
+    if let Some(secs) = args.max_time {
+        builder = builder.max_time(Duration::from_secs(secs));
+    }
+    if let Some(limit) = args.speed_limit {
+        let time = Duration::from_secs(args.speed_time.unwrap_or(30));
+        builder = builder.low_speed_limit(limit, time);
+    }
+    if let Some(secs) = args.read_timeout {
+        builder = builder.read_timeout(Duration::from_secs(secs));
+    }
+    let downloader = match builder.build() {
+        Ok(downloader) => downloader,
+        Err(e) => fail(args.json, e),
+    };
+
+    if args.head {
+        let mut status = 0;
+        for (i, url) in urls.iter().enumerate() {
+            let result = downloader.probe(url);
+            if let (Err(e), 0) = (&result, status) {
+                status = exit_code(e);
+            }
+            match result {
+                Ok(probe) if args.json => println!(
+                    "{}",
+                    json!({
+                        "url": url,
+                        "final_url": probe.url,
+                        "file": probe.file_name,
+                        "size": probe.size,
+                        "accept_ranges": probe.accept_ranges,
+                        "content_type": probe.content_type,
+                        "validator": probe.validator,
+                        "status": "ok",
+                    })
+                ),
+                Ok(probe) => {
+                    if i > 0 {
+                        println!();
+                    }
+                    print_probe(&probe);
+                }
+                Err(e) if args.json => println!(
+                    "{}",
+                    json!({"url": url, "status": "error", "message": e.to_string()})
+                ),
+                Err(e) => eprintln!("Error: {}: {}", url, e),
+            }
+        }
+        std::process::exit(status);
+    }
+
+    // up to --max-concurrent urls are fetched at once, a failure doesn't stop the rest
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    // keeps the lines about one url together
+    let output = Mutex::new(());
+    std::thread::scope(|scope| {
+        for _ in 0..urls.len().min(max_concurrent as usize) {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                if idx >= urls.len() || stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                let url = &urls[idx];
+                let result = match args.metalink {
+                    Some(_) => downloader.download_metalink(url),
+                    None => downloader.download_mirrors(url, &mirrors),
+                };
+                let lines = output.lock().unwrap();
+                print_result(printing, url, &result);
+                drop(lines);
+                // a failed hook fails the url, a download to stdout has no file to hand over
+                let hook = match (&result, args.on_complete.as_deref()) {
+                    (Ok(report), Some(command)) if !to_stdout => {
+                        run_hook(command, args.shell, &report.path)
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(message) = &hook {
+                    let _output = output.lock().unwrap();
+                    eprintln!("Error: {}: {}", url, message);
+                }
+                results.lock().unwrap().push((idx, result, hook.is_ok()));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|&(idx, _, _)| idx);
+    if results
+        .iter()
+        .any(|(_, result, _)| matches!(result, Err(e) if e.kind() == ErrorKind::Interrupted))
+    {
+        if !to_stdout {
+            eprintln!("Run the same command with --continue to resume");
+        }
+        std::process::exit(130);
+    }
+    let mut failed = Vec::new();
+    let mut status = 0;
+    for (idx, result, hook) in &results {
+        let code = match result {
+            Err(e) => exit_code(e),
+            Ok(_) if !hook => 7,
+            Ok(_) => continue,
+        };
+        if failed.is_empty() {
+            status = code;
+        }
+        failed.push(&urls[*idx]);
+    }
+    if urls.len() > 1 && !args.quiet {
+        eprintln!(
+            "Downloaded {} of {} urls",
+            urls.len() - failed.len(),
+            urls.len()
+        );
+        for url in &failed {
+            eprintln!("Failed: {}", url);
+        }
+    }
+    if status != 0 {
+        std::process::exit(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_by_error() {
+        let io = |kind| MgetError::Io(std::io::Error::new(kind, "test"));
+        let http = |status| MgetError::Http {
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            url: String::new(),
+            message: String::new(),
+        };
+        // a reqwest error that needs no network
+        let network = MgetError::Network(reqwest::blocking::get("not a url").unwrap_err());
+        let mismatch = MgetError::ChecksumMismatch {
+            algorithm: "MD5",
+            path: PathBuf::new(),
+            expected: String::new(),
+            actual: String::new(),
+        };
+        let cases = [
+            (io(ErrorKind::InvalidInput), 1),
+            (MgetError::RangeUnsupported { url: String::new() }, 1),
+            (http(304), 1),
+            (network, 2),
+            (io(ErrorKind::TimedOut), 2),
+            (io(ErrorKind::ConnectionRefused), 2),
+            (http(404), 3),
+            (http(416), 3),
+            (http(503), 4),
+            (mismatch, 5),
+            (MgetError::Io(std::io::Error::from_raw_os_error(2)), 6),
+            (io(ErrorKind::PermissionDenied), 6),
+            (io(ErrorKind::Interrupted), 130),
+        ];
+        for (error, code) in cases {
+            assert_eq!(exit_code(&error), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn parse_header_cases() {
+        let cases = [
+            (
+                "Referer: http://example.com/",
+                Some(("Referer", "http://example.com/")),
+            ),
+            ("X-Token:abc", Some(("X-Token", "abc"))),
+            ("  Cookie :  a=1; b=2 ", Some(("Cookie", "a=1; b=2"))),
+            // only the first colon splits
+            ("X-Time: 12:30", Some(("X-Time", "12:30"))),
+            ("X-Empty:", Some(("X-Empty", ""))),
+            ("no colon", None),
+        ];
+        for (value, expected) in cases {
+            let expected = expected.map(|(name, value)| (name.to_string(), value.to_string()));
+            assert_eq!(parse_header(value).ok(), expected, "{:?}", value);
+        }
+        let args = Cli::try_parse_from(["mget", "-H", "A: 1", "--header", "B: 2", "url"]).unwrap();
+        assert_eq!(args.headers.len(), 2);
+        assert!(Cli::try_parse_from(["mget", "-H", "no colon", "url"]).is_err());
+    }
+
+    #[test]
+    fn speed_time_needs_a_limit() {
+        assert!(Cli::try_parse_from(["mget", "--speed-time", "5", "url"]).is_err());
+        let args = Cli::try_parse_from(["mget", "--speed-limit", "1k", "--speed-time", "5", "url"])
+            .unwrap();
+        assert_eq!((args.speed_limit, args.speed_time), (Some(1024), Some(5)));
     }
 }
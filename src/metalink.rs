@@ -0,0 +1,211 @@
+use crate::checksum::Checksum;
+use reqwest::Url;
+use std::io::{Error, ErrorKind};
+
+// urls without a priority come after the ranked ones
+const LOWEST_PRIORITY: u32 = 999_999;
+
+// one file and where to get it, from a metalink or from the command line
+pub(crate) struct Resource {
+    pub(crate) name: Option<String>,
+    pub(crate) size: Option<u64>,
+    // the preferred url first, the rest are used as mirrors
+    pub(crate) urls: Vec<String>,
+    pub(crate) checksums: Vec<Checksum>,
+}
+
+// `.meta4` is Metalink 4 (RFC 5854), `.metalink` the older format most servers publish with it
+pub(crate) fn is_metalink(url: &Url) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    path.ends_with(".meta4") || path.ends_with(".metalink")
+}
+
+// the first <file> of a Metalink 4 document: its name, <size>, sha-256 and md5 <hash>es and
+// <url>s by priority; other elements, further files and XML namespace prefixes are ignored
+pub(crate) fn parse(xml: &str) -> Result<Resource, Error> {
+    let invalid =
+        |msg: &str| Error::new(ErrorKind::InvalidData, format!("Invalid metalink: {}", msg));
+    let mut resource = Resource {
+        name: None,
+        size: None,
+        urls: Vec::new(),
+        checksums: Vec::new(),
+    };
+    let mut urls = Vec::new();
+    let mut files = 0;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let after = &rest[start + 1..];
+        if let Some(comment) = after.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = after.find('>').ok_or_else(|| invalid("unclosed tag"))?;
+        let tag = &after[..end];
+        rest = &after[end + 1..];
+        // declarations, closing tags, and empty elements carry nothing of interest
+        if tag.starts_with(['?', '!', '/']) || (tag.ends_with('/') && !tag.starts_with("file")) {
+            continue;
+        }
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        if name == "file" {
+            files += 1;
+            if files == 1 {
+                resource.name = attribute(attributes, "name");
+            }
+            continue;
+        }
+        if files != 1 {
+            continue;
+        }
+        let text = unescape(rest[..rest.find('<').unwrap_or(rest.len())].trim());
+        match name {
+            "size" => {
+                let size = text.parse().map_err(|_| invalid("size is not a number"))?;
+                resource.size = Some(size);
+            }
+            "hash" => match attribute(attributes, "type").as_deref() {
+                Some("sha-256") => resource.checksums.push(Checksum::Sha256(text)),
+                Some("md5") => resource.checksums.push(Checksum::Md5(text)),
+                _ => {}
+            },
+            "url" => {
+                let priority = attribute(attributes, "priority")
+                    .and_then(|priority| priority.parse().ok())
+                    .unwrap_or(LOWEST_PRIORITY);
+                urls.push((priority, text));
+            }
+            _ => {}
+        }
+    }
+    if files == 0 {
+        return Err(invalid("no <file> element"));
+    }
+    // 1 is the most preferred, the order of the document breaks ties
+    urls.sort_by_key(|&(priority, _)| priority);
+    resource.urls = urls.into_iter().map(|(_, url)| url).collect();
+    if resource.urls.is_empty() {
+        return Err(invalid("no <url> for the file"));
+    }
+    Ok(resource)
+}
+
+// the value of `key="value"` or `key='value'` among the attributes of a tag
+fn attribute(attributes: &str, key: &str) -> Option<String> {
+    let mut rest = attributes;
+    loop {
+        let (name, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| ['"', '\''].contains(c))?;
+        let (value, after) = value[1..].split_once(quote)?;
+        if name.trim() == key {
+            return Some(unescape(value));
+        }
+        rest = after;
+    }
+}
+
+// the predefined entities and numeric character references
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped += &rest[..start];
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .map(|end| &rest[1..end])
+            .and_then(|entity| match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                }
+                .and_then(char::from_u32),
+            });
+        match entity {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[rest.find(';').unwrap_or(0) + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <published>2024-01-01T00:00:00Z</published>
+  <file name="example &amp; co.iso">
+    <size>14471447</size>
+    <hash type="md5">0123456789abcdef0123456789abcdef</hash>
+    <hash type="sha-256">f0ad929cd259957e160ea442eb80986b5f01b6a3bb1cd0b3a1ab7a9c6e5d8e7b</hash>
+    <!-- <url priority="1">http://commented.example.com/example.iso</url> -->
+    <url location="de" priority="2">http://ftp.example.com/example.iso</url>
+    <url>http://last.example.com/example.iso?a=1&amp;b=2</url>
+    <url location="us" priority="1">https://example.com/example.iso</url>
+  </file>
+  <file name="second.iso">
+    <url>http://example.com/second.iso</url>
+  </file>
+</metalink>"#;
+
+    #[test]
+    fn parses_the_first_file() {
+        let resource = parse(SAMPLE).unwrap();
+        assert_eq!(resource.name.as_deref(), Some("example & co.iso"));
+        assert_eq!(resource.size, Some(14471447));
+        assert_eq!(
+            resource.urls,
+            [
+                "https://example.com/example.iso",
+                "http://ftp.example.com/example.iso",
+                "http://last.example.com/example.iso?a=1&b=2",
+            ]
+        );
+        assert!(matches!(
+            resource.checksums.as_slice(),
+            [Checksum::Md5(md5), Checksum::Sha256(sha256)]
+                if md5 == "0123456789abcdef0123456789abcdef" && sha256.starts_with("f0ad929c")
+        ));
+    }
+
+    #[test]
+    fn character_references() {
+        let xml = "<file name='a&#x2F;b&#46;txt'><url>http://example.com/a</url></file>";
+        assert_eq!(parse(xml).unwrap().name.as_deref(), Some("a/b.txt"));
+    }
+
+    #[test]
+    fn invalid_documents() {
+        let cases = [
+            ("<metalink></metalink>", "no <file> element"),
+            (
+                "<file name='a'><size>12</size></file>",
+                "no <url> for the file",
+            ),
+            (
+                "<file><size>big</size><url>x</url></file>",
+                "size is not a number",
+            ),
+            ("<file name='a'", "unclosed tag"),
+        ];
+        for (xml, message) in cases {
+            let error = parse(xml).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), format!("Invalid metalink: {}", message));
+        }
+    }
+}
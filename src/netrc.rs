@@ -0,0 +1,125 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+// the logins of a netrc file by host, as read by curl and ftp
+pub(crate) struct Netrc {
+    machines: Vec<(String, String, String)>,
+    // the `default` entry, for any other host
+    default: Option<(String, String)>,
+}
+
+// `NETRC`, else `~/.netrc`, `_netrc` on windows
+pub(crate) fn default_netrc_path() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    if let Some(path) = var("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    match std::env::consts::OS {
+        "windows" => var("USERPROFILE").map(|home| Path::new(&home).join("_netrc")),
+        _ => var("HOME").map(|home| Path::new(&home).join(".netrc")),
+    }
+}
+
+impl Netrc {
+    pub(crate) fn load(path: &Path) -> Result<Self, Error> {
+        let invalid = |message: String| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Reading netrc {}: {}", path.display(), message),
+            )
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            Error::new(e.kind(), format!("Reading netrc {}: {}", path.display(), e))
+        })?;
+        // passwords others can read aren't secret, ftp refuses such a file too
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(path)?.permissions().mode();
+            if mode & 0o077 != 0 {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!(
+                        "netrc {} is accessible by other users, chmod 600 it",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+        let mut netrc = Netrc {
+            machines: Vec::new(),
+            default: None,
+        };
+        // `machine`, `login` and `password` pairs in any layout; `account` is skipped and a
+        // `macdef` runs to the next empty line
+        let mut entry: Option<(Option<String>, String, String)> = None;
+        let mut finish = |entry: Option<(Option<String>, String, String)>| match entry {
+            Some((Some(host), login, password)) => netrc.machines.push((host, login, password)),
+            Some((None, login, password)) if netrc.default.is_none() => {
+                netrc.default = Some((login, password))
+            }
+            _ => {}
+        };
+        let mut lines = content.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                let mut value = || {
+                    tokens
+                        .next()
+                        .map(str::to_string)
+                        .ok_or_else(|| invalid(format!("{} without a value", token)))
+                };
+                match token {
+                    "machine" => {
+                        let host = value()?.to_ascii_lowercase();
+                        finish(entry.replace((Some(host), String::new(), String::new())));
+                    }
+                    "default" => finish(entry.replace((None, String::new(), String::new()))),
+                    "login" => {
+                        let login = value()?;
+                        if let Some(entry) = &mut entry {
+                            entry.1 = login;
+                        }
+                    }
+                    "password" => {
+                        let password = value()?;
+                        if let Some(entry) = &mut entry {
+                            entry.2 = password;
+                        }
+                    }
+                    "account" => {
+                        value()?;
+                    }
+                    "macdef" => {
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    token if token.starts_with('#') => break,
+                    token => return Err(invalid(format!("unexpected {:?}", token))),
+                }
+            }
+        }
+        finish(entry);
+        Ok(netrc)
+    }
+
+    // the first entry of the host, else the default one
+    pub(crate) fn login(&self, host: &str) -> Option<(&str, &str)> {
+        let host = host.to_ascii_lowercase();
+        self.machines
+            .iter()
+            .find(|(machine, _, _)| *machine == host)
+            .map(|(_, login, password)| (login.as_str(), password.as_str()))
+            .or(self
+                .default
+                .as_ref()
+                .map(|(login, password)| (login.as_str(), password.as_str())))
+    }
+}
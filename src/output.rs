@@ -0,0 +1,282 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error, Read, Seek, SeekFrom, Write},
+};
+
+pub(crate) enum Output {
+    File(File),
+    // preallocated to its full size, all-zero writes are skipped and stay holes; only for a file
+    // that holds no data yet
+    Sparse(File),
+    // can't seek, chunks must arrive in order, e.g. stdout
+    Stream(Box<dyn Write>),
+}
+
+impl Output {
+    pub(crate) fn write_at(&mut self, pos: u64, data: &[u8]) -> Result<(), Error> {
+        match self {
+            Output::File(file) => {
+                file.seek(SeekFrom::Start(pos))?;
+                file.write_all(data)
+            }
+            Output::Sparse(_) if data.iter().all(|&byte| byte == 0) => Ok(()),
+            Output::Sparse(file) => {
+                file.seek(SeekFrom::Start(pos))?;
+                file.write_all(data)
+            }
+            Output::Stream(stream) => stream.write_all(data),
+        }
+    }
+
+    // zero `start` to `end` of a sparse file, where rewritten zeros would be skipped and leave
+    // the old bytes; the others are simply overwritten
+    pub(crate) fn clear(&mut self, start: u64, end: u64) -> Result<(), Error> {
+        match self {
+            Output::Sparse(file) => {
+                file.seek(SeekFrom::Start(start))?;
+                std::io::copy(&mut std::io::repeat(0).take(end - start), file).map(|_| ())
+            }
+            Output::File(_) | Output::Stream(_) => Ok(()),
+        }
+    }
+
+    // reserve the full extent up front instead of growing the file with every out of order write
+    pub(crate) fn preallocate(&mut self, size: u64) -> Result<bool, Error> {
+        match self {
+            Output::File(file) | Output::Sparse(file) => file.set_len(size).map(|_| true),
+            Output::Stream(_) => Ok(false),
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, size: u64) -> Result<(), Error> {
+        match self {
+            Output::File(file) | Output::Sparse(file) => file.set_len(size),
+            Output::Stream(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        match self {
+            Output::File(file) | Output::Sparse(file) => file.flush(),
+            Output::Stream(stream) => stream.flush(),
+        }
+    }
+
+    pub(crate) fn sync(&mut self) -> Result<(), Error> {
+        match self {
+            Output::File(file) | Output::Sparse(file) => file.sync_data(),
+            Output::Stream(_) => Ok(()),
+        }
+    }
+
+    // a second handle to the file for the workers, None for a stream or where there are no
+    // positioned writes
+    pub(crate) fn positioned(&self) -> Result<Option<PositionedFile>, Error> {
+        let (file, sparse) = match self {
+            Output::File(file) => (file, false),
+            Output::Sparse(file) => (file, true),
+            Output::Stream(_) => return Ok(None),
+        };
+        match cfg!(any(unix, windows)) {
+            true => Ok(Some(PositionedFile {
+                file: file.try_clone()?,
+                sparse,
+            })),
+            false => Ok(None),
+        }
+    }
+}
+
+// written by all workers at once, each at its own offset, as no write moves a shared cursor
+pub(crate) struct PositionedFile {
+    file: File,
+    sparse: bool,
+}
+
+impl PositionedFile {
+    pub(crate) fn write_at(&self, pos: u64, data: &[u8]) -> Result<(), Error> {
+        if self.sparse && data.iter().all(|&byte| byte == 0) {
+            return Ok(());
+        }
+        self.write_all_at(pos, data)
+    }
+
+    // like Output::clear
+    pub(crate) fn clear(&self, start: u64, end: u64) -> Result<(), Error> {
+        if !self.sparse {
+            return Ok(());
+        }
+        let zeros = vec![0; std::cmp::min(end - start, 1 << 16) as usize];
+        let mut pos = start;
+        while pos < end {
+            let n = std::cmp::min(end - pos, zeros.len() as u64);
+            self.write_all_at(pos, &zeros[..n as usize])?;
+            pos += n;
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&self, pos: u64, data: &[u8]) -> Result<(), Error> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileExt;
+            self.file.write_all_at(data, pos)
+        }
+        // seek_write may write less, and moves the cursor no one else uses
+        #[cfg(windows)]
+        {
+            use std::{io::ErrorKind, os::windows::fs::FileExt};
+            let (mut pos, mut data) = (pos, data);
+            while !data.is_empty() {
+                match self.file.seek_write(data, pos) {
+                    Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                    Ok(n) => {
+                        data = &data[n..];
+                        pos += n as u64;
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (pos, data, &self.file);
+            Err(Error::from(std::io::ErrorKind::Unsupported))
+        }
+    }
+}
+
+// every write returns once it is on the disk; O_DIRECT would also bypass the page cache but
+// needs writes aligned to the disk blocks, which reads off the network aren't
+pub(crate) fn write_through(options: &mut OpenOptions) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_DSYNC);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_WRITE_THROUGH: u32 = 0x8000_0000;
+        options.custom_flags(FILE_FLAG_WRITE_THROUGH);
+    }
+}
+
+// counts the bytes written since the last sync, for `sync_every`
+pub(crate) struct SyncInterval {
+    every: u64,
+    unsynced: u64,
+}
+
+impl SyncInterval {
+    pub(crate) fn new(every: u64) -> Self {
+        SyncInterval { every, unsynced: 0 }
+    }
+
+    // true when the file should be synced after another `bytes` were written
+    pub(crate) fn written(&mut self, bytes: u64) -> bool {
+        self.unsynced += bytes;
+        if self.unsynced < self.every {
+            return false;
+        }
+        self.unsynced = 0;
+        true
+    }
+}
+
+// deletes a partial download and its state file when dropped, unless it is kept for resuming
+pub(crate) struct PartialFile {
+    pub(crate) paths: Vec<String>,
+    pub(crate) keep: bool,
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            for path in &self.paths {
+                std::fs::remove_file(path).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    // bytes that differ at every offset within a chunk, so a misplaced write shows
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn syncs_at_the_interval() {
+        let mut interval = SyncInterval::new(100);
+        let synced: Vec<bool> = [40, 40, 40, 100, 99, 1, 250, 0]
+            .into_iter()
+            .map(|bytes| interval.written(bytes))
+            .collect();
+        assert_eq!(synced, [false, false, true, true, false, true, true, false]);
+        // every write syncs with an interval of a byte
+        let mut interval = SyncInterval::new(1);
+        assert!((0..5).all(|_| interval.written(1)));
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn positioned_writes_from_many_threads() {
+        let path = temp_path("positioned.bin");
+        let expected = pattern(1 << 20);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let output = Output::File(file);
+        // each thread writes every 8th 4k block, last to first
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let writer = output.positioned().unwrap().unwrap();
+                let expected = &expected;
+                scope.spawn(move || {
+                    for block in (0..expected.len() / 4096).rev().filter(|b| b % 8 == thread) {
+                        let pos = block * 4096;
+                        writer
+                            .write_at(pos as u64, &expected[pos..pos + 4096])
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        drop(output);
+        assert!(std::fs::read(&path).unwrap() == expected);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn sparse_skips_zeros_until_cleared() {
+        let path = temp_path("positioned-sparse.bin");
+        std::fs::write(&path, [b'a'; 30]).unwrap();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        let mut output = Output::Sparse(file);
+        let writer = output.positioned().unwrap().unwrap();
+        writer.write_at(0, &[0; 10]).unwrap();
+        writer.write_at(10, &[0, 1]).unwrap();
+        output.write_at(20, &[0; 5]).unwrap();
+        let mut expected = [b'a'; 30];
+        expected[10..12].copy_from_slice(&[0, 1]);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        writer.clear(0, 5).unwrap();
+        output.clear(20, 25).unwrap();
+        expected[0..5].fill(0);
+        expected[20..25].fill(0);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// how far back the rolling speed looks
+const WINDOW: Duration = Duration::from_secs(1);
+// how often the speed is sampled, often enough for a smooth bar and its peak
+pub(crate) const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+// recent (time, total bytes) samples, so the speed follows the current rate instead of the average
+pub(crate) struct SpeedMeter {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl SpeedMeter {
+    pub(crate) fn new(now: Instant) -> Self {
+        SpeedMeter {
+            samples: VecDeque::from([(now, 0)]),
+        }
+    }
+
+    pub(crate) fn record(&mut self, now: Instant, bytes: u64) {
+        self.samples.push_back((now, bytes));
+        // keep a single sample older than the window as the baseline
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    // bytes per second; the total falls when damaged bytes are rewound, which counts as none
+    pub(crate) fn speed(&self) -> f64 {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(&(start, first)), Some(&(end, last))) if end > start => {
+                last.saturating_sub(first) as f64 / end.duration_since(start).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+// mm:ss, or h:mm:ss for long transfers
+pub(crate) fn format_eta(remaining: u64, speed: f64) -> String {
+    if speed < 1.0 {
+        return "--:--".to_string();
+    }
+    let secs = (remaining as f64 / speed).ceil() as u64;
+    match secs / 3600 {
+        0 => format!("{:02}:{:02}", secs / 60, secs % 60),
+        hours => format!("{}:{:02}:{:02}", hours, secs / 60 % 60, secs % 60),
+    }
+}
+
+// a bar when the total is known, a byte counter with a spinner otherwise; `frame` is None once
+// the download finished
+pub(crate) fn progress_line(
+    received: u64,
+    total: Option<u64>,
+    remaining: u64,
+    rate: f64,
+    frame: Option<usize>,
+    color: bool,
+) -> String {
+    let mb_per_sec = rate / 1024.0 / 1024.0;
+    match total {
+        Some(total) => {
+            let received = std::cmp::min(received, total);
+            let percent = scaled_progress(received, total, 100);
+            let filled_length = scaled_progress(received, total, 50);
+            let filled = "█".repeat(filled_length as usize);
+            let empty = "-".repeat((50 - filled_length) as usize);
+            let percent = format!("{:>3}%", percent);
+            let (bar, percent) = match color {
+                true => (
+                    format!("\x1b[32m{}\x1b[0m{}", filled, empty),
+                    format!("\x1b[1m{}\x1b[0m", percent),
+                ),
+                false => (filled + &empty, percent),
+            };
+            format!(
+                "Progress: |{}| {} {:>7.2} MB/s ETA {:>5}",
+                bar,
+                percent,
+                mb_per_sec,
+                format_eta(remaining, rate)
+            )
+        }
+        None => format!(
+            "Progress: {} {} bytes {:>7.2} MB/s",
+            frame.map_or('=', |frame| ['|', '/', '-', '\\'][frame % 4]),
+            received,
+            mb_per_sec
+        ),
+    }
+}
+
+// a self-contained line for logs: no control characters, each one starting with the UTC time
+// and `label`
+pub(crate) fn log_progress_line(
+    now: SystemTime,
+    label: &str,
+    received: u64,
+    total: Option<u64>,
+    remaining: u64,
+    rate: f64,
+) -> String {
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+        % 86400;
+    let time = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    let mb_per_sec = rate / 1024.0 / 1024.0;
+    match total {
+        Some(total) => format!(
+            "{} {}Progress: {}%, {} of {} bytes, {:.2} MB/s, ETA {}",
+            time,
+            label,
+            scaled_progress(std::cmp::min(received, total), total, 100),
+            received,
+            total,
+            mb_per_sec,
+            format_eta(remaining, rate)
+        ),
+        None => format!(
+            "{} {}Progress: {} bytes, {:.2} MB/s",
+            time, label, received, mb_per_sec
+        ),
+    }
+}
+
+// scale the downloaded/total ratio to 0..=scale, e.g. 100 for percent
+fn scaled_progress(downloaded: u64, total: u64, scale: u64) -> u64 {
+    if total == 0 {
+        return 0;
+    }
+    std::cmp::min(scale * downloaded / total, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_progress_cases() {
+        // downloaded, total, scale, expected
+        let cases = [
+            (0, 100, 100, 0),
+            (1, 3, 100, 33),
+            (99, 100, 100, 99),
+            (100, 100, 100, 100),
+            (150, 100, 100, 100),
+            (20, 40, 50, 25),
+            (5, 0, 100, 0),
+            // a few petabytes
+            (1 << 50, 1 << 52, 100, 25),
+        ];
+        for (downloaded, total, scale, expected) in cases {
+            assert_eq!(scaled_progress(downloaded, total, scale), expected);
+        }
+        assert!(progress_line(50, Some(200), 150, 0.0, None, false).contains(" 25%"));
+    }
+
+    #[test]
+    fn speed_follows_the_window() {
+        let start = Instant::now();
+        let mut meter = SpeedMeter::new(start);
+        meter.record(start + Duration::from_millis(500), 500);
+        assert_eq!(meter.speed(), 1000.0);
+        // the first samples fall out of the window, the rate since then counts
+        meter.record(start + Duration::from_millis(1600), 600);
+        meter.record(start + Duration::from_millis(2600), 2600);
+        assert_eq!(meter.speed(), 2000.0);
+    }
+
+    #[test]
+    fn rewound_bytes_are_no_speed() {
+        let start = Instant::now();
+        let mut meter = SpeedMeter::new(start);
+        meter.record(start + Duration::from_millis(600), 1000);
+        meter.record(start + Duration::from_millis(1700), 1200);
+        // a rewind takes the total below the baseline
+        meter.record(start + Duration::from_millis(1800), 300);
+        assert_eq!(meter.speed(), 0.0);
+    }
+}
@@ -0,0 +1,510 @@
+use crate::{
+    checksum::Checksum, cookies::CookieJar, error::MgetError, filename::sanitize_filename,
+    netrc::Netrc, IpFamily,
+};
+use percent_encoding::percent_decode_str;
+use reqwest::{
+    blocking::{Client, ClientBuilder, RequestBuilder, Response},
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode, Version,
+};
+use std::{
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+// the value of the Authorization header, asked for before every request
+pub(crate) type TokenProvider = Arc<dyn Fn() -> String + Send + Sync>;
+
+// what authorizes a request besides the fixed headers
+#[derive(Clone, Default)]
+pub(crate) struct Auth {
+    // replaces a fixed Authorization header
+    pub(crate) token: Option<TokenProvider>,
+    // Basic auth by host, only loaded without a fixed Authorization header
+    pub(crate) netrc: Option<Arc<Netrc>>,
+}
+
+// the provider's value replaces any fixed Authorization header, the netrc login of the host
+// applies to urls without credentials of their own
+pub(crate) fn authorize(request: RequestBuilder, auth: &Auth, url: &str) -> RequestBuilder {
+    let Some(token) = &auth.token else {
+        let login = auth.netrc.as_ref().and_then(|netrc| {
+            let url = reqwest::Url::parse(url).ok()?;
+            match url.username() {
+                "" => netrc.login(url.host_str()?),
+                _ => None,
+            }
+        });
+        return match login {
+            Some((login, password)) => request.basic_auth(login, Some(password)),
+            None => request,
+        };
+    };
+    let value = token();
+    match HeaderValue::from_str(&value) {
+        Ok(mut value) => {
+            value.set_sensitive(true);
+            request.header(reqwest::header::AUTHORIZATION, value)
+        }
+        // sending fails with reqwest's own error
+        Err(_) => request.header(reqwest::header::AUTHORIZATION, value),
+    }
+}
+
+pub(crate) struct ClientOptions {
+    pub(crate) max_redirects: usize,
+    pub(crate) connect_timeout: Option<Duration>,
+    // applies to sending the request and to every single read of the body
+    pub(crate) read_timeout: Option<Duration>,
+    // sent with every request, the probes included
+    pub(crate) headers: HeaderMap,
+    // ask for and transparently decode gzip, brotli and deflate bodies
+    pub(crate) compressed: bool,
+    // replaces the HTTP_PROXY, HTTPS_PROXY and ALL_PROXY environment variables
+    pub(crate) proxy: Option<String>,
+    // skip certificate verification
+    pub(crate) insecure: bool,
+    // a PEM file with extra root certificates
+    pub(crate) ca_cert: Option<PathBuf>,
+    // sends the fixed cookies and keeps the ones responses set
+    pub(crate) cookies: Option<Arc<CookieJar>>,
+    // the schemes a redirect may lead to, None for anything but https to http
+    pub(crate) redirect_protocols: Option<Vec<&'static str>>,
+    // offer h2 over TLS, so the workers share one multiplexed connection when the server agrees
+    pub(crate) http2: bool,
+    // a local address or interface name the connections go out from
+    pub(crate) interface: Option<String>,
+    // only connect to addresses of this family
+    pub(crate) ip_family: Option<IpFamily>,
+    // hosts pinned to addresses instead of asking DNS, a host may appear several times
+    pub(crate) resolve: Vec<(String, IpAddr)>,
+}
+
+impl ClientOptions {
+    // a redirect never downgrades https to http unless the protocols allow it explicitly
+    fn redirect_policy(&self) -> reqwest::redirect::Policy {
+        let (max_redirects, allowed) = (self.max_redirects, self.redirect_protocols.clone());
+        reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                let message = format!("Too many redirects, last url: {}", attempt.url());
+                return attempt.error(message);
+            }
+            let from = attempt.previous().last().map_or("", |url| url.scheme());
+            let to = attempt.url().scheme();
+            let refused = match &allowed {
+                Some(allowed) => !allowed.contains(&to),
+                None => from == "https" && to == "http",
+            };
+            match refused {
+                true => {
+                    let message = format!(
+                        "Redirect from {} to {} refused, {} is not allowed",
+                        attempt.previous().last().map_or("", |url| url.as_str()),
+                        attempt.url(),
+                        to
+                    );
+                    attempt.error(message)
+                }
+                false => attempt.follow(),
+            }
+        })
+    }
+
+    pub(crate) fn build(&self) -> Result<Client, Error> {
+        let mut builder = Client::builder();
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid proxy {}: {}", proxy, e),
+                )
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_cert {
+            let invalid = |e: &dyn std::fmt::Display| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid CA certificate {}: {}", path.display(), e),
+                )
+            };
+            let pem = std::fs::read(path).map_err(|e| invalid(&e))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| invalid(&e))? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(cookies) = &self.cookies {
+            builder = builder.cookie_provider(cookies.clone());
+        }
+        let local = self
+            .interface
+            .as_ref()
+            .and_then(|interface| interface.parse::<IpAddr>().ok());
+        if let Some(interface) = &self.interface {
+            builder = bind_interface(builder, interface)?;
+        }
+        // with a local address of one family only, the connector skips the resolved addresses
+        // of the other
+        match (self.ip_family, local) {
+            (Some(family), Some(addr)) if addr.is_ipv4() != (family == IpFamily::V4) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Local address {} is of the other IP family", addr),
+                ))
+            }
+            (Some(IpFamily::V4), None) => {
+                builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+            }
+            (Some(IpFamily::V6), None) => {
+                builder = builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+            }
+            _ => {}
+        }
+        // all addresses of a host go in one override, a later one would replace the earlier; port
+        // 0 keeps the port of the url
+        let mut pinned: Vec<(String, Vec<SocketAddr>)> = Vec::new();
+        for (host, addr) in &self.resolve {
+            let host = host.to_ascii_lowercase();
+            let addr = SocketAddr::new(*addr, 0);
+            match pinned.iter_mut().find(|(pinned, _)| *pinned == host) {
+                Some((_, addrs)) => addrs.push(addr),
+                None => pinned.push((host, vec![addr])),
+            }
+        }
+        for (host, addrs) in &pinned {
+            builder = builder.resolve_to_addrs(host, addrs);
+        }
+        // otherwise every worker gets a connection of its own
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+        builder
+            .redirect(self.redirect_policy())
+            .default_headers(self.headers.clone())
+            .gzip(self.compressed)
+            .brotli(self.compressed)
+            .deflate(self.compressed)
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.read_timeout)
+            .danger_accept_invalid_certs(self.insecure)
+            .build()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+    }
+}
+
+// an address must be assigned to this host, a name must be one of its interfaces
+fn bind_interface(builder: ClientBuilder, interface: &str) -> Result<ClientBuilder, Error> {
+    if let Ok(addr) = interface.parse::<IpAddr>() {
+        UdpSocket::bind((addr, 0)).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Can't bind to local address {}: {}", addr, e),
+            )
+        })?;
+        return Ok(builder.local_address(addr));
+    }
+    #[cfg(any(
+        target_os = "android",
+        target_os = "fuchsia",
+        target_os = "illumos",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "solaris",
+        target_os = "tvos",
+        target_os = "visionos",
+        target_os = "watchos",
+    ))]
+    {
+        // only linux lists its interfaces in sysfs, elsewhere a bad name fails on connect
+        if cfg!(any(target_os = "linux", target_os = "android"))
+            && !Path::new("/sys/class/net").join(interface).exists()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("No network interface named {}", interface),
+            ));
+        }
+        Ok(builder.interface(interface))
+    }
+    #[cfg(not(any(
+        target_os = "android",
+        target_os = "fuchsia",
+        target_os = "illumos",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "solaris",
+        target_os = "tvos",
+        target_os = "visionos",
+        target_os = "watchos",
+    )))]
+    {
+        let _ = builder;
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Binding to an interface by name isn't supported here, give the address of {}",
+                interface
+            ),
+        ))
+    }
+}
+
+pub(crate) fn request_error(e: reqwest::Error) -> Error {
+    // the policy's own message, without reqwest's wrapping
+    if e.is_redirect() {
+        let message = std::error::Error::source(&e).map_or_else(
+            || "Redirect refused".to_string(),
+            |source| source.to_string(),
+        );
+        return Error::new(ErrorKind::InvalidData, message);
+    }
+    MgetError::Network(e).into()
+}
+
+// reqwest reports body errors as `Other`, keep timeouts apart from broken connections
+pub(crate) fn read_error(e: Error) -> Error {
+    let timed_out = e.kind() == ErrorKind::TimedOut
+        || e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<reqwest::Error>())
+            .is_some_and(|inner| inner.is_timeout());
+    match timed_out {
+        true => Error::new(ErrorKind::TimedOut, "Timed out reading response body"),
+        false => Error::new(ErrorKind::ConnectionReset, e),
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RemoteFile {
+    // the final url after redirects
+    pub(crate) url: String,
+    // None when the server sent no usable Content-Length, e.g. for a decoded body
+    pub(crate) size: Option<u64>,
+    pub(crate) accept_ranges: bool,
+    // sanitized filename suggested by Content-Disposition
+    pub(crate) file_name: Option<String>,
+    pub(crate) content_type: Option<String>,
+    // a strong ETag or else Last-Modified, sent as If-Range so a changed resource isn't mixed in
+    pub(crate) validator: Option<String>,
+    // hashes the file is known to have, e.g. from a metalink
+    pub(crate) checksums: Vec<Checksum>,
+    // the HTTP version of the probe, None for ftp and local files
+    pub(crate) version: Option<Version>,
+}
+
+// `timeout` bounds each of the probe requests on top of the client's timeouts
+pub(crate) fn get_file_size(
+    client: &Client,
+    url: &str,
+    timeout: Option<Duration>,
+    auth: &Auth,
+) -> Result<RemoteFile, MgetError> {
+    // a one byte range tells the size and whether ranges work at once, unlike a HEAD whose
+    // Accept-Ranges a server may not live up to
+    let request = client.get(url).header(reqwest::header::RANGE, "bytes=0-0");
+    let mut request = authorize(request, auth, url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().map_err(request_error)?;
+    // ranges work but the total is `*`, unknown, unless HEAD has a Content-Length
+    let mut unknown_total = None;
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let total = header_value(&response, reqwest::header::CONTENT_RANGE)
+                .and_then(|value| value.rsplit('/').next())
+                .map(str::to_string);
+            let size = total.as_deref().and_then(|total| total.parse().ok());
+            if size.is_some() || total.as_deref() == Some("*") {
+                let remote = remote_file(&response, size, true);
+                // read to the end, so the connection goes back to the pool for the workers
+                response.bytes().ok();
+                match size {
+                    Some(_) => return Ok(remote),
+                    None => unknown_total = Some(remote),
+                }
+            }
+        }
+        // the range was ignored, dropping the response stops the body
+        StatusCode::OK => {
+            let size = header_value(&response, reqwest::header::CONTENT_LENGTH)
+                .and_then(|value| value.parse().ok());
+            return Ok(remote_file(&response, size, false));
+        }
+        _ => {}
+    }
+
+    // refused ranged GETs and e.g. GETs of an empty file, HEAD may still know the size
+    let mut request = authorize(client.head(url), auth, url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().map_err(request_error)?;
+
+    if let Some(remote) = unknown_total {
+        // a HEAD that failed adds nothing, the file is streamed until it ends
+        let size = header_value(&response, reqwest::header::CONTENT_LENGTH)
+            .and_then(|value| value.parse().ok())
+            .filter(|_| response.status().is_success());
+        return Ok(RemoteFile { size, ..remote });
+    }
+    if !response.status().is_success() {
+        return Err(MgetError::Http {
+            status: response.status(),
+            url: response.url().to_string(),
+            message: format!("Probing {} failed: {}", response.url(), response.status()),
+        });
+    }
+    let file_size = header_value(&response, reqwest::header::CONTENT_LENGTH)
+        .and_then(|value| value.parse().ok());
+    let accept_ranges = header_value(&response, reqwest::header::ACCEPT_RANGES)
+        .is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+    Ok(remote_file(&response, file_size, accept_ranges))
+}
+
+fn header_value(response: &Response, name: HeaderName) -> Option<&str> {
+    response.headers().get(name)?.to_str().ok()
+}
+
+// the name, type and validator of a probe response
+fn remote_file(response: &Response, size: Option<u64>, accept_ranges: bool) -> RemoteFile {
+    let file_name = header_value(response, reqwest::header::CONTENT_DISPOSITION)
+        .and_then(disposition_filename)
+        .and_then(|name| sanitize_filename(&name));
+    let content_type = header_value(response, reqwest::header::CONTENT_TYPE).map(str::to_string);
+    // weak ETags can't be used with If-Range
+    let validator = [reqwest::header::ETAG, reqwest::header::LAST_MODIFIED]
+        .into_iter()
+        .filter_map(|name| header_value(response, name))
+        .find(|value| !value.starts_with("W/"))
+        .map(str::to_string);
+    RemoteFile {
+        url: response.url().to_string(),
+        size,
+        accept_ranges,
+        file_name,
+        content_type,
+        validator,
+        checksums: Vec::new(),
+        version: Some(response.version()),
+    }
+}
+
+// the `filename` parameter of e.g. `attachment; filename="report.pdf"`, or the RFC 5987
+// `filename*=UTF-8''report%20(1).pdf` that takes precedence over it
+fn disposition_filename(value: &str) -> Option<String> {
+    // split on `;` outside of quoted strings
+    let mut params = Vec::new();
+    let (mut current, mut quoted, mut escaped) = (String::new(), false, false);
+    for c in value.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    params.push(current);
+
+    let param = |name: &str| {
+        params.iter().skip(1).find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().to_string())
+        })
+    };
+    if let Some(name) = param("filename*").and_then(|value| decode_ext_value(&value)) {
+        return Some(name);
+    }
+    param("filename").map(
+        |value| match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(inner) => {
+                let mut unescaped = String::new();
+                let mut chars = inner.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => unescaped.extend(chars.next()),
+                        c => unescaped.push(c),
+                    }
+                }
+                unescaped
+            }
+            None => value,
+        },
+    )
+}
+
+// `charset'language'percent-encoded`, in UTF-8 or ISO-8859-1 as RFC 8187 allows
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let bytes: Vec<u8> = percent_decode_str(encoded).collect();
+    match charset.to_ascii_lowercase().as_str() {
+        "utf-8" => String::from_utf8(bytes).ok(),
+        // every byte is the code point of the same value
+        "iso-8859-1" => Some(bytes.into_iter().map(char::from).collect()),
+        _ => None,
+    }
+}
+
+// bytes [start, end) of `url`, only from a server that honors the range
+pub(crate) fn fetch_bytes(
+    client: &Client,
+    url: &str,
+    start: u64,
+    end: u64,
+    timeout: Option<Duration>,
+    auth: &Auth,
+) -> Result<Vec<u8>, MgetError> {
+    let range = format!("bytes={}-{}", start, end - 1);
+    let request = client.get(url).header(reqwest::header::RANGE, range);
+    let mut request = authorize(request, auth, url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().map_err(request_error)?;
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => Ok(response.bytes().map_err(request_error)?.to_vec()),
+        status if !status.is_success() => Err(MgetError::Http {
+            status,
+            url: url.to_string(),
+            message: format!("Fetching bytes {}-{} failed: {}", start, end - 1, status),
+        }),
+        _ => Err(MgetError::RangeUnsupported {
+            url: url.to_string(),
+        }),
+    }
+}
+
+// the size a GET sees, from the total in `Content-Range: bytes 0-0/1234`
+pub(crate) fn probe_length(
+    client: &Client,
+    url: &str,
+    timeout: Option<Duration>,
+    auth: &Auth,
+) -> Option<u64> {
+    let request = client.get(url).header(reqwest::header::RANGE, "bytes=0-0");
+    let mut request = authorize(request, auth, url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().ok()?;
+    let header = match response.status() {
+        StatusCode::PARTIAL_CONTENT => reqwest::header::CONTENT_RANGE,
+        StatusCode::OK => reqwest::header::CONTENT_LENGTH,
+        _ => return None,
+    };
+    let value = response.headers().get(header)?.to_str().ok()?;
+    value.rsplit('/').next()?.parse().ok()
+}
@@ -0,0 +1,314 @@
+use std::{
+    fs::OpenOptions,
+    io::{Error, ErrorKind, Write},
+};
+
+// insert [start, end) into the sorted list of completed ranges, merging neighbours
+pub(crate) fn add_range(ranges: &mut Vec<(u64, u64)>, start: u64, end: u64) {
+    ranges.push((start, end));
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = std::cmp::max(last.1, end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *ranges = merged;
+}
+
+// cut [start, end) out of the ranges, splitting the one it is in
+pub(crate) fn remove_range(ranges: &mut Vec<(u64, u64)>, start: u64, end: u64) {
+    *ranges = ranges
+        .iter()
+        .flat_map(|&(from, to)| [(from, to.min(start)), (from.max(end), to)])
+        .filter(|(from, to)| from < to)
+        .collect();
+}
+
+// the ranges of [0, file_size) not covered by the completed ones
+pub(crate) fn missing_ranges(completed: &[(u64, u64)], file_size: u64) -> Vec<(u64, u64)> {
+    let mut missing = Vec::new();
+    let mut pos = 0;
+    for &(start, end) in completed {
+        if start > pos {
+            missing.push((pos, start));
+        }
+        pos = std::cmp::max(pos, end);
+    }
+    if pos < file_size {
+        missing.push((pos, file_size));
+    }
+    missing
+}
+
+// split the ranges into (pos, length) chunks of at most `chunk_size` bytes
+pub(crate) fn split_chunks(ranges: &[(u64, u64)], chunk_size: u64) -> Vec<(u64, u64)> {
+    let chunk_size = std::cmp::max(chunk_size, 1);
+    let mut chunks = Vec::new();
+    for &(start, end) in ranges {
+        let mut pos = start;
+        while pos < end {
+            let length = std::cmp::min(chunk_size, end - pos);
+            chunks.push((pos, length));
+            pos += length;
+        }
+    }
+    chunks
+}
+
+// fewer threads than asked for when each would get less than `min_split` bytes, at least one
+pub(crate) fn split_threads(size: u64, threads: usize, min_split: u64) -> usize {
+    match size.checked_div(min_split) {
+        Some(parts) => parts.clamp(1, threads as u64) as usize,
+        None => threads,
+    }
+}
+
+pub(crate) fn state_file_name(file_name: &str) -> String {
+    format!("{}.mget", file_name)
+}
+
+// the parts of the ranges not covered by `other`
+fn subtract(ranges: &[(u64, u64)], other: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut left = ranges.to_vec();
+    for &(start, end) in other {
+        remove_range(&mut left, start, end);
+    }
+    left
+}
+
+// the state file holds the file size and the validator, if any, on the first line, then one
+// completed `start-end` range per line. Ranges are appended as they complete and merged on
+// load, so a killed download still leaves a record of everything but the chunks in flight
+pub(crate) struct StateFile {
+    path: String,
+    header: String,
+    // what the file records so far
+    saved: Vec<(u64, u64)>,
+    // fsync every record, so it survives a power loss as well
+    sync: bool,
+}
+
+impl StateFile {
+    // write the file from scratch, the ranges of an earlier run merged
+    pub(crate) fn create(
+        path: &str,
+        file_size: u64,
+        validator: Option<&str>,
+        completed: &[(u64, u64)],
+        sync: bool,
+    ) -> Result<Self, Error> {
+        let mut state = StateFile {
+            path: path.to_string(),
+            header: match validator {
+                Some(validator) => format!("{} {}\n", file_size, validator),
+                None => format!("{}\n", file_size),
+            },
+            saved: Vec::new(),
+            sync,
+        };
+        state.rewrite(completed)?;
+        Ok(state)
+    }
+
+    fn rewrite(&mut self, completed: &[(u64, u64)]) -> Result<(), Error> {
+        let mut content = self.header.clone();
+        for (start, end) in completed {
+            content += &format!("{}-{}\n", start, end);
+        }
+        let mut file = std::fs::File::create(&self.path)?;
+        file.write_all(content.as_bytes())?;
+        if self.sync {
+            file.sync_data()?;
+        }
+        self.saved = completed.to_vec();
+        Ok(())
+    }
+
+    // append what completed since the last record; only a range given up again, whose bytes
+    // are downloaded once more, rewrites the whole file
+    pub(crate) fn record(&mut self, completed: &[(u64, u64)]) -> Result<(), Error> {
+        if !subtract(&self.saved, completed).is_empty() {
+            return self.rewrite(completed);
+        }
+        let new = subtract(completed, &self.saved);
+        if new.is_empty() {
+            return Ok(());
+        }
+        let mut records = String::new();
+        for &(start, end) in &new {
+            records += &format!("{}-{}\n", start, end);
+            add_range(&mut self.saved, start, end);
+        }
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        file.write_all(records.as_bytes())?;
+        if self.sync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) type State = (u64, Option<String>, Vec<(u64, u64)>);
+
+pub(crate) fn load_state(path: &str) -> Result<State, Error> {
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid state file: {}", path),
+        )
+    };
+    let mut content = std::fs::read_to_string(path)?;
+    // a record cut short by a crash, its range is downloaded again
+    if !content.ends_with('\n') {
+        content.truncate(content.rfind('\n').map_or(0, |end| end + 1));
+    }
+    let mut lines = content.lines();
+    let first = lines.next().ok_or_else(invalid)?;
+    let (file_size, validator) = match first.split_once(' ') {
+        Some((size, validator)) => (size, Some(validator.to_string())),
+        None => (first, None),
+    };
+    let file_size = file_size.parse().map_err(|_| invalid())?;
+    let mut completed = Vec::new();
+    for line in lines {
+        let (start, end) = line
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+            .ok_or_else(invalid)?;
+        if start > end || end > file_size {
+            return Err(invalid());
+        }
+        add_range(&mut completed, start, end);
+    }
+    Ok((file_size, validator, completed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    #[test]
+    fn chunks_cover_the_ranges() {
+        // ranges, chunk size
+        let cases = [
+            (vec![(0, 100)], 10),
+            (vec![(0, 105)], 10),
+            (vec![(0, 7)], 10),
+            (vec![(5, 20), (40, 41), (60, 100)], 16),
+            (vec![(0, 3)], 0),
+            (vec![], 10),
+        ];
+        for (ranges, chunk_size) in cases {
+            let chunks = split_chunks(&ranges, chunk_size);
+            // in order, one after another, each inside a range and none too long
+            let mut covered = Vec::new();
+            for &(pos, length) in &chunks {
+                assert!(length > 0 && length <= chunk_size.max(1), "{:?}", chunks);
+                assert!(ranges
+                    .iter()
+                    .any(|&(start, end)| pos >= start && pos + length <= end));
+                assert!(
+                    covered.last().is_none_or(|&(_, end)| end <= pos),
+                    "{:?}",
+                    chunks
+                );
+                covered.push((pos, pos + length));
+            }
+            let mut merged = Vec::new();
+            for (start, end) in covered {
+                add_range(&mut merged, start, end);
+            }
+            let mut expected = Vec::new();
+            for &(start, end) in &ranges {
+                add_range(&mut expected, start, end);
+            }
+            assert_eq!(merged, expected);
+        }
+    }
+
+    #[test]
+    fn threads_for_the_size() {
+        const MB: u64 = 1024 * 1024;
+        // size, threads, min split, expected
+        let cases = [
+            (4096, 8, MB, 1),
+            (0, 8, MB, 1),
+            (3 * MB, 8, MB, 3),
+            (3 * MB - 1, 8, MB, 2),
+            (100 * MB, 8, MB, 8),
+            (100 * MB, 1, MB, 1),
+            // no minimum, every thread is used
+            (4096, 8, 0, 8),
+        ];
+        for (size, threads, min_split, expected) in cases {
+            assert_eq!(
+                split_threads(size, threads, min_split),
+                expected,
+                "{} bytes",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn state_survives_a_crash() {
+        let path = temp_path("crash.mget");
+        let path = path.to_str().unwrap();
+        let mut state = StateFile::create(path, 100, Some("\"etag\""), &[(0, 10)], false).unwrap();
+        let mut completed = vec![(0, 10)];
+        for (start, end) in [(30, 40), (10, 20), (70, 80)] {
+            add_range(&mut completed, start, end);
+            state.record(&completed).unwrap();
+        }
+        // each completed range is appended, nothing is rewritten
+        let content = std::fs::read_to_string(path).unwrap();
+        assert_eq!(content, "100 \"etag\"\n0-10\n30-40\n10-20\n70-80\n");
+        // killed in the middle of the next record
+        drop(state);
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(b"50-6")
+            .unwrap();
+        let (size, validator, loaded) = load_state(path).unwrap();
+        assert_eq!((size, validator.as_deref()), (100, Some("\"etag\"")));
+        assert_eq!(loaded, [(0, 20), (30, 40), (70, 80)]);
+        assert_eq!(
+            missing_ranges(&loaded, size),
+            [(20, 30), (40, 70), (80, 100)]
+        );
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn rewound_range_rewrites_the_state() {
+        let path = temp_path("rewind.mget");
+        let path = path.to_str().unwrap();
+        let mut state = StateFile::create(path, 100, None, &[(0, 50)], false).unwrap();
+        state.record(&[(0, 20), (30, 50)]).unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "100\n0-20\n30-50\n");
+        assert_eq!(load_state(path).unwrap().2, [(0, 20), (30, 50)]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn invalid_state_is_refused() {
+        let path = temp_path("invalid.mget");
+        for content in [
+            "",
+            "x\n",
+            "100\n0-200\n",
+            "100\n20-10\n",
+            "100\nsome-thing\n",
+        ] {
+            std::fs::write(&path, content).unwrap();
+            let error = load_state(path.to_str().unwrap()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", content);
+        }
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    io::{Error, ErrorKind},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const TOKENS: [&str; 5] = ["host", "filename", "ext", "index", "date"];
+
+// an output path with `{token}` placeholders, checked once when the downloader is built
+pub(crate) struct Template(String);
+
+// the values a template is expanded with for one url
+pub(crate) struct Fields<'a> {
+    pub(crate) host: &'a str,
+    pub(crate) filename: &'a str,
+    // the position of the url in the batch, starting at 1
+    pub(crate) index: usize,
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Self, Error> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let invalid = |msg: &str| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} in output template {:?}", msg, template),
+                )
+            };
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("Unclosed {"))?;
+            let token = &rest[start + 1..start + end];
+            if !TOKENS.contains(&token) {
+                return Err(invalid(&format!("Unknown token {{{}}}", token)));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(Template(template.to_string()))
+    }
+
+    pub(crate) fn expand(&self, fields: &Fields) -> String {
+        let ext = match fields.filename.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => ext,
+            _ => "",
+        };
+        self.0
+            .replace("{host}", fields.host)
+            .replace("{filename}", fields.filename)
+            .replace("{ext}", ext)
+            .replace("{index}", &fields.index.to_string())
+            .replace("{date}", &today())
+    }
+}
+
+// the UTC date as YYYY-MM-DD
+pub(crate) fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    civil_date(secs)
+}
+
+fn civil_date(secs: u64) -> String {
+    // days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_every_token() {
+        let fields = Fields {
+            host: "example.com",
+            filename: "archive.tar.gz",
+            index: 3,
+        };
+        let cases = [
+            ("{host}/{filename}", "example.com/archive.tar.gz"),
+            ("{index}-{filename}", "3-archive.tar.gz"),
+            ("out.{ext}", "out.gz"),
+            ("{host}/{host}", "example.com/example.com"),
+            ("plain", "plain"),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(Template::parse(template).unwrap().expand(&fields), expected);
+        }
+        let dated = Template::parse("{date}/{filename}")
+            .unwrap()
+            .expand(&fields);
+        assert_eq!(dated, format!("{}/archive.tar.gz", today()));
+        // a dotfile has no extension
+        let fields = Fields {
+            filename: ".bashrc",
+            ..fields
+        };
+        assert_eq!(Template::parse("x{ext}").unwrap().expand(&fields), "x");
+    }
+
+    #[test]
+    fn unknown_tokens_are_refused() {
+        let cases = [
+            ("{name}", "Unknown token {name}"),
+            ("{host}/{Host}", "Unknown token {Host}"),
+            ("{}", "Unknown token {}"),
+            ("{host", "Unclosed {"),
+        ];
+        for (template, message) in cases {
+            let error = Template::parse(template).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            assert!(error.to_string().starts_with(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn civil_dates() {
+        // seconds since the epoch, date
+        let cases = [
+            (0, "1970-01-01"),
+            (86399, "1970-01-01"),
+            (951_782_400, "2000-02-29"),
+            (1_709_164_800, "2024-02-29"),
+            (1_735_689_599, "2024-12-31"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(civil_date(secs), expected);
+        }
+    }
+}
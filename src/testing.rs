@@ -0,0 +1,146 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::Arc,
+};
+
+// a request as the test server saw it
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // the `bytes=start-end` range, inclusive
+    pub(crate) fn range(&self) -> Option<(u64, u64)> {
+        let (start, end) = self
+            .header("Range")?
+            .strip_prefix("bytes=")?
+            .split_once('-')?;
+        Some((start.parse().ok()?, end.parse().ok()?))
+    }
+}
+
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn status(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    // the requested range of `body`, or all of it without a Range header
+    pub(crate) fn ranged(request: &Request, body: &[u8]) -> Self {
+        match request.range() {
+            Some((start, end)) => {
+                let end = std::cmp::min(end, body.len() as u64 - 1);
+                Response {
+                    status: 206,
+                    headers: vec![(
+                        "Content-Range".to_string(),
+                        format!("bytes {}-{}/{}", start, end, body.len()),
+                    )],
+                    body: body[start as usize..=end as usize].to_vec(),
+                }
+            }
+            None => Response::full(body),
+        }
+    }
+
+    // all of `body`, whatever was asked for
+    pub(crate) fn full(body: &[u8]) -> Self {
+        Response {
+            status: 200,
+            headers: vec![("Accept-Ranges".to_string(), "bytes".to_string())],
+            body: body.to_vec(),
+        }
+    }
+}
+
+// serve `respond` on a local port, one thread per connection, and return the base url; the
+// server lives as long as the test process
+pub(crate) fn serve(respond: impl Fn(&Request) -> Response + Send + Sync + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let respond = Arc::new(respond);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let respond = respond.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).ok();
+                let mut parts = line.split_whitespace();
+                let mut request = Request {
+                    method: parts.next().unwrap_or_default().to_string(),
+                    headers: Vec::new(),
+                };
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        request
+                            .headers
+                            .push((name.trim().to_string(), value.trim().to_string()));
+                    }
+                }
+                let response = respond(&request);
+                // a chunked body goes without a Content-Length
+                let chunked = response
+                    .headers
+                    .iter()
+                    .any(|(name, value)| name == "Transfer-Encoding" && value == "chunked");
+                let mut head = format!("HTTP/1.1 {} X\r\nConnection: close\r\n", response.status);
+                if !chunked {
+                    head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+                }
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                head.push_str("\r\n");
+                let mut stream = stream;
+                stream.write_all(head.as_bytes()).ok();
+                if request.method == "HEAD" {
+                    return;
+                }
+                if !chunked {
+                    stream.write_all(&response.body).ok();
+                    return;
+                }
+                for chunk in response.body.chunks(4096) {
+                    stream
+                        .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                        .ok();
+                    stream.write_all(chunk).ok();
+                    stream.write_all(b"\r\n").ok();
+                }
+                stream.write_all(b"0\r\n\r\n").ok();
+            });
+        }
+    });
+    url
+}
+
+// a path in the temp dir no other test uses, removed first if it already exists
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mget-test-{}-{}", std::process::id(), name));
+    std::fs::remove_file(&path).ok();
+    path
+}
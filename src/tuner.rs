@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+// connections an adaptive download starts with
+pub(crate) const START_THREADS: usize = 2;
+// long enough for a new connection to get past its slow start
+const INTERVAL: Duration = Duration::from_secs(2);
+// less than this much faster counts as a plateau
+const MIN_GAIN: f64 = 1.1;
+
+pub(crate) enum Step {
+    Keep,
+    // open one more connection
+    Grow,
+    // close one connection, the last one added didn't pay off or errors rose
+    Shrink,
+}
+
+// adds connections one at a time while the aggregate speed keeps climbing, and settles for good
+// once it stops, so the count can't run away
+pub(crate) struct ThreadTuner {
+    max: usize,
+    workers: usize,
+    since: Instant,
+    bytes: u64,
+    best: f64,
+    failures: usize,
+    settled: bool,
+}
+
+impl ThreadTuner {
+    pub(crate) fn new(now: Instant, workers: usize, max: usize) -> Self {
+        ThreadTuner {
+            max,
+            workers,
+            since: now,
+            bytes: 0,
+            best: 0.0,
+            failures: 0,
+            settled: workers >= max,
+        }
+    }
+
+    pub(crate) fn workers(&self) -> usize {
+        self.workers
+    }
+
+    // bytes per second over the last interval, none if rewound bytes made the total fall
+    pub(crate) fn speed(&self, now: Instant, downloaded: u64) -> f64 {
+        downloaded.saturating_sub(self.bytes) as f64 / now.duration_since(self.since).as_secs_f64()
+    }
+
+    // `downloaded` and `failures` are running totals; `queued` tells whether a new connection
+    // would find a chunk to take
+    pub(crate) fn step(
+        &mut self,
+        now: Instant,
+        downloaded: u64,
+        failures: usize,
+        queued: bool,
+    ) -> Step {
+        if self.settled || now.duration_since(self.since) < INTERVAL {
+            return Step::Keep;
+        }
+        let speed = self.speed(now, downloaded);
+        let failed = failures > self.failures;
+        (self.since, self.bytes, self.failures) = (now, downloaded, failures);
+        if failed || speed < self.best * MIN_GAIN {
+            self.settled = true;
+            if self.workers > 1 {
+                self.workers -= 1;
+                return Step::Shrink;
+            }
+            return Step::Keep;
+        }
+        // the rest of the chunks are already taken
+        if !queued {
+            self.settled = true;
+            return Step::Keep;
+        }
+        self.best = speed;
+        self.workers += 1;
+        self.settled = self.workers >= self.max;
+        Step::Grow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_while_faster_and_settles() {
+        let start = Instant::now();
+        let mut tuner = ThreadTuner::new(start, 2, 8);
+        assert!(matches!(
+            tuner.step(start + INTERVAL / 2, 100, 0, true),
+            Step::Keep
+        ));
+        assert!(matches!(
+            tuner.step(start + INTERVAL, 2000, 0, true),
+            Step::Grow
+        ));
+        assert_eq!(tuner.workers(), 3);
+        // barely faster, the last connection goes and the count stays
+        assert!(matches!(
+            tuner.step(start + INTERVAL * 2, 4100, 0, true),
+            Step::Shrink
+        ));
+        assert_eq!(tuner.workers(), 2);
+        assert!(matches!(
+            tuner.step(start + INTERVAL * 3, 9000, 0, true),
+            Step::Keep
+        ));
+    }
+
+    #[test]
+    fn falling_total_is_no_speed() {
+        let start = Instant::now();
+        let mut tuner = ThreadTuner::new(start, 2, 8);
+        tuner.step(start + INTERVAL, 2000, 0, true);
+        assert_eq!(tuner.speed(start + INTERVAL * 2, 1500), 0.0);
+        assert!(matches!(
+            tuner.step(start + INTERVAL * 2, 1500, 0, true),
+            Step::Shrink
+        ));
+    }
+}
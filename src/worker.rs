@@ -0,0 +1,500 @@
+use crate::{
+    checksum::BodyDigest,
+    clock::{Clock, Jitter},
+    data,
+    error::MgetError,
+    file,
+    ftp::{self, FtpOptions},
+    limiter::RateLimiter,
+    output::PositionedFile,
+    remote::{authorize, read_error, request_error, Auth},
+};
+use reqwest::blocking::Client;
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind, Read},
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::SyncSender,
+        Arc, Mutex, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+pub(crate) enum TaskResult {
+    // a worker, numbered from 0 in the order they were started, took the chunk off the queue
+    Started(usize, usize),
+    // the buffer goes back to the pool once written and counted
+    Downloading(usize, u64, Vec<u8>),
+    Failed(usize, MgetError),
+    // the bytes [start, end) of the chunk failed the digest check and are downloaded again
+    Rewind(usize, u64, u64),
+    Done(usize),
+}
+
+// the byte range a worker is responsible for
+pub(crate) struct Task {
+    pub(crate) idx: usize,
+    pub(crate) url: String,
+    pub(crate) pos: u64,
+    pub(crate) length: u64,
+    // false when the server can't serve ranges and the whole body is streamed
+    pub(crate) ranged: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct RetryPolicy {
+    pub(crate) retries: usize,
+    // doubled after every failed attempt
+    pub(crate) wait: Duration,
+    pub(crate) jitter: Arc<Jitter>,
+    // the response statuses worth another attempt, the others fail the chunk at once
+    pub(crate) statuses: Vec<u16>,
+}
+
+// the longest wait between two attempts, however many failed before
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+impl RetryPolicy {
+    // 5xx, 408 Request Timeout and 429 Too Many Requests
+    pub(crate) fn default_statuses() -> Vec<u16> {
+        (500..600).chain([408, 429]).collect()
+    }
+
+    // network errors, timeouts and the listed statuses
+    pub(crate) fn is_retriable(&self, e: &MgetError) -> bool {
+        match e {
+            MgetError::Http { status, .. } => self.statuses.contains(&status.as_u16()),
+            e => matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::TimedOut),
+        }
+    }
+
+    // the wait after `attempt` failed retries, capped so a large wait or count can't overflow:
+    // between half and all of the doubled wait
+    pub(crate) fn backoff(&self, attempt: usize) -> Duration {
+        let wait = u32::try_from(attempt)
+            .ok()
+            .and_then(|attempt| 2u32.checked_pow(attempt))
+            .and_then(|factor| self.wait.checked_mul(factor))
+            .map_or(MAX_BACKOFF, |wait| wait.min(MAX_BACKOFF));
+        wait / 2 + (wait / 2).mul_f64(self.jitter.fraction())
+    }
+}
+
+// read buffers handed back by the writer, so reads stop allocating once the pool is warm
+pub(crate) struct BufferPool {
+    size: usize,
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub(crate) fn new(size: usize) -> Self {
+        BufferPool {
+            size,
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn take(&self) -> Vec<u8> {
+        let mut buffer = self.buffers.lock().unwrap().pop().unwrap_or_default();
+        buffer.resize(self.size, 0);
+        buffer
+    }
+
+    pub(crate) fn put(&self, buffer: Vec<u8>) {
+        self.buffers.lock().unwrap().push(buffer);
+    }
+}
+
+// shared by all workers of a download
+pub(crate) struct TaskContext {
+    // chunks not yet taken by a worker
+    pub(crate) queue: Mutex<VecDeque<Task>>,
+    pub(crate) client: Client,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) limiter: Option<RateLimiter>,
+    // where reading stops even without a range, for a prefix of a stream
+    pub(crate) limit: Option<u64>,
+    // where the output starts in the remote file, added to every requested position
+    pub(crate) offset: u64,
+    pub(crate) buffers: BufferPool,
+    pub(crate) validator: Option<String>,
+    // set for ftp:// and ftps:// urls, which are read over suppaftp instead of the http client
+    pub(crate) ftp: Option<FtpOptions>,
+    // set for file:// urls, which are read from the disk
+    pub(crate) local: bool,
+    // checked before every chunk, so workers stop soon after an interrupt
+    pub(crate) stop: Arc<AtomicBool>,
+    // set when the download gives up on its own, e.g. past the deadline
+    pub(crate) abort: AtomicBool,
+    // the end of `max_time`, no request outlives it, so the threads can be joined in time
+    pub(crate) deadline: Option<Instant>,
+    // workers that should stop after their current chunk, when the tuner drops connections
+    pub(crate) retire: AtomicUsize,
+    // requests repeated after a transient error, for the report
+    pub(crate) retries: AtomicUsize,
+    pub(crate) auth: Auth,
+    pub(crate) clock: Arc<dyn Clock>,
+    // the output file of a download of known size; the workers write their reads into it
+    // themselves, in parallel, and the coordinator only records what was written
+    pub(crate) writer: Option<PositionedFile>,
+}
+
+// the threads of a download; dropping it stops them and waits for them to end, so none outlives
+// the download, which must give up its receiver first to unblock their sends
+pub(crate) struct Workers {
+    ctx: Arc<TaskContext>,
+    handles: Vec<JoinHandle<()>>,
+    // set by the first wait for the workers to stop, the later ones share what is left
+    deadline: OnceLock<Instant>,
+}
+
+impl Workers {
+    pub(crate) fn new(ctx: Arc<TaskContext>) -> Self {
+        Workers {
+            ctx,
+            handles: Vec::new(),
+            deadline: OnceLock::new(),
+        }
+    }
+
+    // whether the workers still get time to stop, SHUTDOWN_GRACE in all from the first call
+    pub(crate) fn in_grace(&self) -> bool {
+        let clock = &self.ctx.clock;
+        clock.now() < *self.deadline.get_or_init(|| clock.now() + SHUTDOWN_GRACE)
+    }
+
+    // workers are numbered as they start, replacements and added ones included
+    pub(crate) fn spawn(&mut self, tx: &SyncSender<TaskResult>) {
+        let (tx, ctx, worker) = (tx.clone(), self.ctx.clone(), self.handles.len());
+        let handle = std::thread::spawn(move || download_part(tx, ctx, worker));
+        self.handles.push(handle);
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.handles.iter().all(JoinHandle::is_finished)
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.ctx.abort.store(true, Ordering::Relaxed);
+        // a read from a dead connection without a read timeout may never return, such a
+        // worker is left to end on its own
+        while !self.finished() && self.in_grace() {
+            self.ctx.clock.sleep(Duration::from_millis(10));
+        }
+        for handle in self.handles.drain(..) {
+            if handle.is_finished() {
+                handle.join().ok();
+            }
+        }
+    }
+}
+
+// how long the workers of a finished or failed download get to notice it
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+// take chunks off the queue until it is empty, so faster connections end up doing more of them
+pub(crate) fn download_part(tx: SyncSender<TaskResult>, ctx: Arc<TaskContext>, worker: usize) {
+    loop {
+        let retired = ctx
+            .retire
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        if retired.is_ok() {
+            return;
+        }
+        let Some(task) = ctx.queue.lock().unwrap().pop_front() else {
+            return;
+        };
+        let idx = task.idx;
+        if tx.send(TaskResult::Started(idx, worker)).is_err() {
+            return;
+        }
+        // a panic fails the chunk like any error, instead of leaving it undone forever
+        let attempt = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            download_part_inner(tx.clone(), &ctx, task)
+        }));
+        let result = match attempt {
+            Ok(Ok(_)) => TaskResult::Done(idx),
+            Ok(Err(e)) => TaskResult::Failed(idx, e),
+            Err(panic) => {
+                let message = match panic.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+                };
+                let message = format!("Thread {} panicked: {}", worker, message);
+                TaskResult::Failed(idx, Error::other(message).into())
+            }
+        };
+        let failed = matches!(result, TaskResult::Failed(..));
+        // the download is abandoned after a failure, leave the rest of the queue alone
+        if tx.send(result).is_err() || failed {
+            return;
+        }
+    }
+}
+
+// how much of an error response body ends up in the message
+const ERROR_BODY_CHARS: usize = 200;
+
+fn download_part_inner(
+    tx: SyncSender<TaskResult>,
+    ctx: &TaskContext,
+    task: Task,
+) -> Result<u64, MgetError> {
+    let retry = &ctx.retry;
+    let end = task.pos + task.length;
+    let mut pos = task.pos;
+    let mut attempt = 0;
+    // a token that expired during the download gets one retry of its own
+    let mut reauthorized = false;
+    loop {
+        let result = match &ctx.ftp {
+            Some(options) => ftp::fetch_range(ctx, options, &tx, &task, &mut pos, end),
+            None if ctx.local && task.url.starts_with("data:") => {
+                data::fetch_range(ctx, &tx, &task, &mut pos, end)
+            }
+            None if ctx.local => file::fetch_range(ctx, &tx, &task, &mut pos, end),
+            None => fetch_range(ctx, &tx, &task, &mut pos, end),
+        }
+        .map_err(MgetError::from);
+        match result {
+            Ok(()) => return Ok(pos),
+            // the whole range arrived before the connection broke
+            Err(_) if task.ranged && pos >= end => return Ok(pos),
+            // a plain stream can't continue from `pos`, so only ranged requests retry
+            Err(e)
+                if (task.ranged || pos == task.pos)
+                    && attempt < retry.retries
+                    && retry.is_retriable(&e)
+                    && !ctx.abort.load(Ordering::Relaxed) =>
+            {
+                ctx.clock.sleep(retry.backoff(attempt));
+                attempt += 1;
+                ctx.retries.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(MgetError::Http { status, .. })
+                if status == reqwest::StatusCode::UNAUTHORIZED
+                    && ctx.auth.token.is_some()
+                    && !reauthorized =>
+            {
+                reauthorized = true;
+                ctx.retries.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// request [pos, end) and forward the body, advancing `pos` as chunks arrive
+fn fetch_range(
+    ctx: &TaskContext,
+    tx: &SyncSender<TaskResult>,
+    task: &Task,
+    pos: &mut u64,
+    end: u64,
+) -> Result<(), Error> {
+    let mut request = authorize(ctx.client.get(&task.url), &ctx.auth, &task.url);
+    if task.ranged {
+        let (from, to) = (*pos + ctx.offset, end - 1 + ctx.offset);
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-{}", from, to));
+        if let Some(validator) = &ctx.validator {
+            request = request.header(reqwest::header::IF_RANGE, validator);
+        }
+    }
+    if let Some(deadline) = ctx.deadline {
+        request = request.timeout(deadline.saturating_duration_since(ctx.clock.now()));
+    }
+    let mut response = request.send().map_err(request_error)?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let mut message = status.to_string();
+        if task.ranged {
+            message += &format!(" for bytes {}-{}", *pos + ctx.offset, end - 1 + ctx.offset);
+        }
+        // error pages can be whole html documents, the start is enough to tell what went wrong
+        let body = response.text().unwrap_or_default();
+        let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !body.is_empty() {
+            let mut excerpt: String = body.chars().take(ERROR_BODY_CHARS).collect();
+            if excerpt.len() < body.len() {
+                excerpt.push_str("...");
+            }
+            message += &format!(": {}", excerpt);
+        }
+        return Err(MgetError::Http {
+            status,
+            url: task.url.clone(),
+            message,
+        }
+        .into());
+    }
+    if task.ranged && response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        // If-Range answers with the full body when the validator no longer matches
+        if ctx.validator.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Resource changed during the download, restart it",
+            ));
+        }
+        // a full body written at a non-zero offset would corrupt the file
+        if *pos + ctx.offset > 0 {
+            return Err(MgetError::RangeUnsupported {
+                url: task.url.clone(),
+            }
+            .into());
+        }
+    }
+    let full = response.status() != reqwest::StatusCode::PARTIAL_CONTENT;
+    let Some(digest) = BodyDigest::from_headers(response.headers(), full) else {
+        return forward_body(ctx, tx, task, &mut response, pos, end);
+    };
+    let (start, length) = (*pos, response.content_length());
+    let mut body = DigestReader {
+        inner: &mut response,
+        digest,
+        read: 0,
+    };
+    forward_body(ctx, tx, task, &mut body, pos, end)?;
+    // a body cut short at `end` or the byte limit can't be compared
+    if Some(body.read) != length {
+        return Ok(());
+    }
+    let what = match full {
+        true => "the response".to_string(),
+        false => format!("bytes {}-{}", start + ctx.offset, *pos - 1 + ctx.offset),
+    };
+    body.digest.verify(&what).inspect_err(|_| {
+        // the damaged bytes are written again by the retry, which skips the zeros of a sparse file
+        if let Some(writer) = &ctx.writer {
+            writer.clear(start, *pos).ok();
+        }
+        tx.send(TaskResult::Rewind(task.idx, start, *pos)).ok();
+        *pos = start;
+    })
+}
+
+// hashes what passes through for the digest check
+struct DigestReader<'a> {
+    inner: &'a mut dyn Read,
+    digest: BodyDigest,
+    read: u64,
+}
+
+impl Read for DigestReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.digest.update(&buf[..n]);
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+// send the body to the writer in pooled buffers, stopping at `end` for ranged tasks and at the
+// limit for the others
+pub(crate) fn forward_body(
+    ctx: &TaskContext,
+    tx: &SyncSender<TaskResult>,
+    task: &Task,
+    body: &mut dyn Read,
+    pos: &mut u64,
+    end: u64,
+) -> Result<(), Error> {
+    loop {
+        if ctx.stop.load(Ordering::Relaxed) || ctx.abort.load(Ordering::Relaxed) {
+            return Err(Error::new(ErrorKind::Interrupted, "Download interrupted"));
+        }
+        let mut buffer = ctx.buffers.take();
+        if let Some(limiter) = &ctx.limiter {
+            limiter.wait();
+        }
+        let stop_at = match task.ranged {
+            true => Some(end),
+            false => ctx.limit,
+        };
+        if let Some(stop_at) = stop_at {
+            let left = stop_at.saturating_sub(*pos);
+            buffer.truncate(std::cmp::min(buffer.len() as u64, left) as usize);
+        }
+        let n = body.read(&mut buffer).map_err(read_error)?;
+        if n == 0 {
+            ctx.buffers.put(buffer);
+            return Ok(());
+        }
+        if let Some(limiter) = &ctx.limiter {
+            limiter.consume(n);
+        }
+
+        buffer.truncate(n);
+        if let Some(writer) = &ctx.writer {
+            writer.write_at(*pos, &buffer)?;
+        }
+        tx.send(TaskResult::Downloading(task.idx, *pos, buffer))
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Failed to send download event"))?;
+        *pos += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(wait: Duration, seed: u64) -> RetryPolicy {
+        RetryPolicy {
+            retries: 3,
+            wait,
+            jitter: Arc::new(Jitter::new(seed)),
+            statuses: RetryPolicy::default_statuses(),
+        }
+    }
+
+    // the wait before its jitter is taken off
+    fn jittered(wait: Duration, full: Duration) -> bool {
+        wait >= full / 2 && wait <= full
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let retry = policy(Duration::from_millis(500), 1);
+        for (attempt, full) in [500, 1000, 2000, 4000, 8000].into_iter().enumerate() {
+            let wait = retry.backoff(attempt);
+            assert!(jittered(wait, Duration::from_millis(full)), "{:?}", wait);
+        }
+        assert!(jittered(retry.backoff(7), MAX_BACKOFF));
+        assert!(jittered(retry.backoff(usize::MAX), MAX_BACKOFF));
+        // --retry-wait 18446744073709551615
+        let retry = policy(Duration::from_millis(u64::MAX), 1);
+        assert!(jittered(retry.backoff(0), MAX_BACKOFF));
+        assert!(jittered(retry.backoff(3), MAX_BACKOFF));
+    }
+
+    #[test]
+    fn backoff_repeats_its_seed() {
+        let schedule = |seed| {
+            let retry = policy(Duration::from_millis(400), seed);
+            (0..5).map(|n| retry.backoff(n)).collect::<Vec<_>>()
+        };
+        assert_eq!(schedule(7), schedule(7));
+        assert_ne!(schedule(7), schedule(8));
+    }
+
+    #[test]
+    fn retriable_errors() {
+        let retry = policy(Duration::ZERO, 1);
+        let http = |status: u16| MgetError::Http {
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            url: String::new(),
+            message: String::new(),
+        };
+        assert!(retry.is_retriable(&http(503)));
+        assert!(retry.is_retriable(&http(429)));
+        assert!(!retry.is_retriable(&http(404)));
+        let io = |kind| MgetError::Io(Error::from(kind));
+        assert!(retry.is_retriable(&io(ErrorKind::TimedOut)));
+        assert!(!retry.is_retriable(&io(ErrorKind::PermissionDenied)));
+    }
+}
@@ -0,0 +1,77 @@
+// runs the mget binary against the test server of the library's own tests
+#[allow(dead_code)]
+#[path = "../src/testing.rs"]
+mod testing;
+
+use std::process::{Command, Output};
+use testing::{serve, temp_path, Response};
+
+fn mget(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mget_rs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn output_dash_pipes_the_bytes_in_order() {
+    let body: Vec<u8> = (0..300_000).map(|i| (i * 7 % 251) as u8).collect();
+    let url = {
+        let body = body.clone();
+        serve(move |request| Response::ranged(request, &body))
+    };
+    let output = mget(&["--threads", "4", "--verbose", "--output", "-", &url]);
+    assert!(output.status.success());
+    // the progress went to stderr
+    assert!(output.stdout == body);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Downloaded successfully"));
+}
+
+#[test]
+fn json_is_the_only_output() {
+    let body = vec![7; 50_000];
+    let url = serve(move |request| Response::ranged(request, &body));
+    let path = temp_path("json.bin");
+    let output = mget(&["--json", "--output", path.to_str().unwrap(), &url]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["status"], "ok");
+    assert_eq!(report["bytes"], 50_000);
+    assert_eq!(report["file"], path.to_str().unwrap());
+    std::fs::remove_file(path).ok();
+
+    let missing = serve(|_| Response::status(404));
+    let output = mget(&["--json", &missing]);
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["status"], "error");
+    assert!(report["message"].is_string());
+}
+
+#[test]
+fn exit_codes_tell_the_failures_apart() {
+    let url = serve(|request| Response::ranged(request, &[b'a'; 100]));
+    let missing = serve(|_| Response::status(404));
+    let broken = serve(|_| Response::status(503));
+    let path = temp_path("exit.bin");
+    let output = path.to_str().unwrap();
+    let sha256 = "0".repeat(64);
+    // a file can't be a directory
+    let blocker = temp_path("exit-blocker");
+    std::fs::write(&blocker, "").unwrap();
+    let unwritable = blocker.join("exit.bin");
+    let cases: [(&[&str], i32); 6] = [
+        (&["--output", output, &url], 0),
+        (&["--retries", "0", "http://127.0.0.1:1/"], 2),
+        (&["--output", output, &missing], 3),
+        (&["--retries", "0", "--output", output, &broken], 4),
+        (&["--sha256", &sha256, "--output", output, &url], 5),
+        (&["--output", unwritable.to_str().unwrap(), &url], 6),
+    ];
+    for (args, code) in cases {
+        let status = mget(args).status;
+        assert_eq!(status.code(), Some(code), "{:?}", args);
+        std::fs::remove_file(&path).ok();
+    }
+    std::fs::remove_file(blocker).ok();
+}